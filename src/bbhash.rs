@@ -0,0 +1,163 @@
+use super::bits::{BitVec, Rank};
+use super::hash::{splitmix64, FOHash};
+use std::cmp::max;
+
+// https://arxiv.org/pdf/1702.03154
+
+// Bits per key of each level, trades size against the number of levels a lookup goes through
+pub const DEFAULT_GAMMA: f64 = 2.0;
+
+// Keys still colliding after that many levels are reported as stuck
+pub const MAX_LEVELS: usize = 64;
+
+/// Cascade of bit arrays: each level holds a bit for every key it hashes alone to a position,
+/// the colliding keys go to the next level. The index of a key is the rank of its bit.
+#[derive(Debug, Clone, Default)]
+pub struct BBHash {
+    pub gamma: f64,
+    /// Seed of the first-order hash of each level
+    pub level_seeds: Vec<u64>,
+    /// First bit of each level, followed by the total number of bits
+    pub level_offsets: Vec<usize>,
+    pub bits: BitVec,
+    pub rank: Rank,
+}
+
+impl BBHash {
+    // Position of a fingerprint in a level, the fingerprint is hashed as 8 little-endian bytes
+    fn level_hash(fo_hash: &FOHash, fingerprint: u64, seed: u64, size: usize) -> usize {
+        return (fo_hash.hash_with_seed(&fingerprint.to_le_bytes(), seed).as_u64() % size as u64) as usize;
+    }
+
+    /// Builds the levels from the fingerprints of the keys, which must be distinct.
+    /// Returns the fingerprints still colliding after MAX_LEVELS levels on failure
+    pub fn new(
+        fingerprints: Vec<u64>,
+        fo_hash: &FOHash,
+        gamma: f64,
+        stage_seed: u64,
+        jobs: usize,
+        verbose: bool,
+    ) -> Result<BBHash, Vec<u64>> {
+        let mut bbhash = BBHash {
+            gamma,
+            level_offsets: vec![0],
+            ..Default::default()
+        };
+
+        let total = fingerprints.len();
+        let mut remaining = fingerprints;
+
+        while !remaining.is_empty() {
+            if bbhash.level_seeds.len() == MAX_LEVELS {
+                return Err(remaining);
+            }
+
+            let level = bbhash.level_seeds.len();
+            let size = max(64, ((remaining.len() as f64 * gamma).ceil() as usize).next_multiple_of(64));
+
+            let mut seed = splitmix64(stage_seed ^ level as u64);
+
+            if !fo_hash.is_64bits() {
+                seed = seed as u32 as u64;
+            }
+
+            let positions = BBHash::level_positions(&remaining, fo_hash, seed, size, jobs);
+
+            let mut seen = BitVec::with_len(size);
+            let mut collided = BitVec::with_len(size);
+
+            for &pos in positions.iter() {
+                if seen.get(pos) {
+                    collided.set(pos);
+                }
+
+                seen.set(pos);
+            }
+
+            for pos in 0..size {
+                bbhash.bits.push(seen.get(pos) && !collided.get(pos));
+            }
+
+            remaining = remaining
+                .into_iter()
+                .zip(positions)
+                .filter(|&(_, pos)| collided.get(pos))
+                .map(|(fingerprint, _)| fingerprint)
+                .collect();
+
+            bbhash.level_seeds.push(seed);
+            bbhash.level_offsets.push(bbhash.bits.len());
+
+            if verbose {
+                print!(
+                    "\rLevel {}: {} bits, {}/{} keys placed   ",
+                    level,
+                    size,
+                    total - remaining.len(),
+                    total
+                );
+            }
+        }
+
+        if verbose {
+            println!();
+        }
+
+        bbhash.rank = Rank::new(&bbhash.bits);
+
+        return Ok(bbhash);
+    }
+
+    fn level_positions(fingerprints: &[u64], fo_hash: &FOHash, seed: u64, size: usize, jobs: usize) -> Vec<usize> {
+        if jobs <= 1 {
+            return fingerprints
+                .iter()
+                .map(|&fingerprint| BBHash::level_hash(fo_hash, fingerprint, seed, size))
+                .collect();
+        }
+
+        return std::thread::scope(|scope| {
+            let handles: Vec<_> = fingerprints
+                .chunks(max(1, fingerprints.len().div_ceil(jobs)))
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|&fingerprint| BBHash::level_hash(fo_hash, fingerprint, seed, size))
+                            .collect::<Vec<usize>>()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().expect("Hashing thread panicked"))
+                .collect()
+        });
+    }
+
+    /// Index of a fingerprint, None if it is not found in any level
+    pub fn index(&self, fo_hash: &FOHash, fingerprint: u64) -> Option<usize> {
+        for (level, &seed) in self.level_seeds.iter().enumerate() {
+            let offset = self.level_offsets[level];
+            let size = self.level_offsets[level + 1] - offset;
+            let pos = offset + BBHash::level_hash(fo_hash, fingerprint, seed, size);
+
+            if self.bits.get(pos) {
+                return Some(self.rank.rank(&self.bits, pos));
+            }
+        }
+
+        return None;
+    }
+
+    pub fn num_levels(&self) -> usize {
+        return self.level_seeds.len();
+    }
+
+    /// Size of the bit arrays and rank samples in bits
+    pub fn size_bits(&self) -> usize {
+        return self.bits.len() + self.rank.size_bits();
+    }
+}
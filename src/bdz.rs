@@ -0,0 +1,203 @@
+use super::bits::{BitVec, RANK_SAMPLE_WORDS};
+use super::hash::{splitmix64, FOHash};
+
+// https://cmph.sourceforge.net/papers/wads07.pdf
+
+// Vertices per key, the hypergraph is peelable with high probability above 1.222
+const VERTICES_PER_KEY: f64 = 1.23;
+
+// Peeling fails with a small probability, each attempt hashes the keys with other seeds
+pub const MAX_ATTEMPTS: u64 = 100;
+
+// g-value of the vertices that are not assigned to any key
+const UNASSIGNED: u64 = 3;
+
+// Low bit of every 2-bits g-value of a word
+const LOW_BITS: u64 = 0x5555555555555555;
+
+/// 3-uniform hypergraph with a vertex per key hash: every key is an edge, and the g-values of
+/// its three vertices select the one it is assigned to, whose rank gives the index of the key
+#[derive(Debug, Clone, Default)]
+pub struct Bdz {
+    /// Seed of the first-order hash of each of the three parts of the vertices
+    pub vertex_seeds: Vec<u64>,
+    /// Number of vertices of each part
+    pub part_size: usize,
+    /// 2-bits g-value of every vertex
+    pub g: BitVec,
+    /// Number of assigned vertices before every RANK_SAMPLE_WORDS-th word of g
+    pub ranks: Vec<u32>,
+    pub attempts: u64,
+}
+
+impl Bdz {
+    /// Vertices of a fingerprint, one in each part
+    pub fn edge(fo_hash: &FOHash, fingerprint: u64, seeds: &[u64], part_size: usize) -> [usize; 3] {
+        let bytes = fingerprint.to_le_bytes();
+
+        return [0, 1, 2].map(|i| {
+            i * part_size + (fo_hash.hash_with_seed(&bytes, seeds[i]).as_u64() % part_size as u64) as usize
+        });
+    }
+
+    /// Builds the hypergraph of the fingerprints of the keys, which must be distinct.
+    /// Returns the number of attempts if no attempt gave a peelable hypergraph
+    pub fn new(fingerprints: &[u64], fo_hash: &FOHash, stage_seed: u64) -> Result<Bdz, u64> {
+        let part_size = Bdz::part_size(fingerprints.len());
+
+        for attempt in 0..MAX_ATTEMPTS {
+            let vertex_seeds = Bdz::vertex_seeds(fo_hash, stage_seed, attempt);
+
+            let edges: Vec<[usize; 3]> = fingerprints
+                .iter()
+                .map(|&fingerprint| Bdz::edge(fo_hash, fingerprint, &vertex_seeds, part_size))
+                .collect();
+
+            let Some(order) = Bdz::peel(&edges, 3 * part_size) else {
+                continue;
+            };
+
+            let mut g = vec![UNASSIGNED; 3 * part_size];
+
+            // In reverse peeling order, the free vertex of an edge is not used by the edges
+            // assigned after it, so the g-values summed below are final
+            for &(edge, free) in order.iter().rev() {
+                let vertices = edges[edge];
+                let j = vertices.iter().position(|&v| v == free).expect("The free vertex belongs to the edge") as u64;
+                let sum: u64 = vertices.iter().filter(|&&v| v != free).map(|&v| g[v]).sum();
+
+                g[free] = (j + 3 - sum % 3) % 3;
+            }
+
+            let mut bdz = Bdz {
+                vertex_seeds,
+                part_size,
+                attempts: attempt + 1,
+                ..Default::default()
+            };
+
+            for value in g {
+                bdz.g.push_bits(value, 2);
+            }
+
+            let mut count = 0;
+
+            for (i, &word) in bdz.g.words().iter().enumerate() {
+                if i % RANK_SAMPLE_WORDS == 0 {
+                    bdz.ranks.push(count);
+                }
+
+                count += Bdz::assigned_in_word(word, 32);
+            }
+
+            return Ok(bdz);
+        }
+
+        return Err(MAX_ATTEMPTS);
+    }
+
+    /// Number of vertices of each part for n keys. Always odd, the first-order hashes whose low
+    /// bits only depend on the low bits of their input, like FNV-1a, would not spread the
+    /// vertices of small hypergraphs over a power of two
+    pub fn part_size(n: usize) -> usize {
+        return ((n as f64 * VERTICES_PER_KEY / 3.0).ceil() as usize).max(2) | 1;
+    }
+
+    /// Seeds of the three parts of the vertices, different for each attempt
+    pub fn vertex_seeds(fo_hash: &FOHash, stage_seed: u64, attempt: u64) -> Vec<u64> {
+        return (0..3)
+            .map(|i| {
+                let seed = splitmix64(stage_seed ^ (attempt * 3 + i));
+
+                if fo_hash.is_64bits() {
+                    return seed;
+                }
+
+                return seed as u32 as u64;
+            })
+            .collect();
+    }
+
+    /// Peels the hypergraph by repeatedly removing an edge with a vertex of degree 1.
+    /// Returns the removed edges with their free vertex, None if a 2-core remains
+    pub fn peel(edges: &[[usize; 3]], num_vertices: usize) -> Option<Vec<(usize, usize)>> {
+        let mut degrees = vec![0u32; num_vertices];
+        let mut incident = vec![0usize; num_vertices];
+
+        for (e, vertices) in edges.iter().enumerate() {
+            for &v in vertices.iter() {
+                degrees[v] += 1;
+                incident[v] ^= e;
+            }
+        }
+
+        let mut stack: Vec<usize> = (0..num_vertices).filter(|&v| degrees[v] == 1).collect();
+        let mut order = Vec::with_capacity(edges.len());
+
+        while let Some(v) = stack.pop() {
+            if degrees[v] != 1 {
+                continue;
+            }
+
+            // The xor of the incident edges is the only edge left
+            let e = incident[v];
+            order.push((e, v));
+
+            for &u in edges[e].iter() {
+                degrees[u] -= 1;
+                incident[u] ^= e;
+
+                if degrees[u] == 1 {
+                    stack.push(u);
+                }
+            }
+        }
+
+        if order.len() < edges.len() {
+            return None;
+        }
+
+        return Some(order);
+    }
+
+    // Number of g-values other than UNASSIGNED among the first n of a word
+    fn assigned_in_word(word: u64, n: usize) -> u32 {
+        let unassigned = word & (word >> 1) & LOW_BITS;
+        let mask = if n == 32 { u64::MAX } else { (1 << (2 * n)) - 1 };
+
+        return n as u32 - (unassigned & mask).count_ones();
+    }
+
+    fn g_value(&self, vertex: usize) -> u64 {
+        return (self.g.words()[vertex / 32] >> (2 * (vertex % 32))) & 3;
+    }
+
+    /// Index of a fingerprint, None if it selects an unassigned vertex
+    pub fn index(&self, fo_hash: &FOHash, fingerprint: u64) -> Option<usize> {
+        let vertices = Bdz::edge(fo_hash, fingerprint, &self.vertex_seeds, self.part_size);
+        let j = vertices.iter().map(|&v| self.g_value(v)).sum::<u64>() % 3;
+        let vertex = vertices[j as usize];
+
+        if self.g_value(vertex) == UNASSIGNED {
+            return None;
+        }
+
+        let word = vertex / 32;
+        let first = word - word % RANK_SAMPLE_WORDS;
+
+        let mut rank = self.ranks[word / RANK_SAMPLE_WORDS];
+
+        for &w in self.g.words()[first..word].iter() {
+            rank += Bdz::assigned_in_word(w, 32);
+        }
+
+        rank += Bdz::assigned_in_word(self.g.words()[word], vertex % 32);
+
+        return Some(rank as usize);
+    }
+
+    /// Size of the g-values and rank samples in bits
+    pub fn size_bits(&self) -> usize {
+        return self.g.len() + self.ranks.len() * 32;
+    }
+}
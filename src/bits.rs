@@ -0,0 +1,289 @@
+// Bit-level storage shared by the succinct table layouts
+
+// Number of ones between two select samples of an Elias-Fano sequence
+pub const SELECT_SAMPLE_RATE: usize = 64;
+
+/// Growable array of bits, stored in 64-bits words
+#[derive(Debug, Clone, Default)]
+pub struct BitVec {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitVec {
+    pub fn new() -> BitVec {
+        return BitVec::default();
+    }
+
+    pub fn with_len(len: usize) -> BitVec {
+        return BitVec {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    pub fn words(&self) -> &Vec<u64> {
+        return &self.words;
+    }
+
+    pub fn get(&self, pos: usize) -> bool {
+        return (self.words[pos / 64] >> (pos % 64)) & 1 == 1;
+    }
+
+    pub fn set(&mut self, pos: usize) {
+        self.words[pos / 64] |= 1 << (pos % 64);
+    }
+
+    /// Appends the lowest `width` bits of value, least significant first
+    pub fn push_bits(&mut self, value: u64, width: usize) {
+        for i in 0..width {
+            self.push((value >> i) & 1 == 1);
+        }
+    }
+
+    pub fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(64) {
+            self.words.push(0);
+        }
+
+        if bit {
+            self.words[self.len / 64] |= 1 << (self.len % 64);
+        }
+
+        self.len += 1;
+    }
+
+    /// Appends value in unary: value zeros followed by a one
+    pub fn push_unary(&mut self, value: u64) {
+        for _ in 0..value {
+            self.push(false);
+        }
+
+        self.push(true);
+    }
+}
+
+/// Elias-Fano encoding of a non-decreasing sequence of integers
+#[derive(Debug, Clone, Default)]
+pub struct EliasFano {
+    /// Number of low bits of each value stored verbatim
+    pub width: usize,
+    pub lower: BitVec,
+    /// High bits of the values, the i-th one is at position (value >> width) + i
+    pub upper: BitVec,
+    /// Position in upper of every SELECT_SAMPLE_RATE-th one
+    pub samples: Vec<u32>,
+}
+
+impl EliasFano {
+    pub fn new(values: &[u64]) -> EliasFano {
+        let n = values.len().max(1) as u64;
+        let universe = values.last().copied().unwrap_or(0);
+
+        let width = if universe > n { (universe / n).ilog2() as usize } else { 0 };
+
+        let mut lower = BitVec::new();
+        let mut upper = BitVec::with_len((universe >> width) as usize + values.len() + 1);
+        let mut samples = Vec::new();
+
+        for (i, &value) in values.iter().enumerate() {
+            let pos = (value >> width) as usize + i;
+
+            if i % SELECT_SAMPLE_RATE == 0 {
+                samples.push(pos as u32);
+            }
+
+            lower.push_bits(value, width);
+            upper.set(pos);
+        }
+
+        return EliasFano {
+            width,
+            lower,
+            upper,
+            samples,
+        };
+    }
+
+    pub fn size_bits(&self) -> usize {
+        return self.lower.len() + self.upper.len() + self.samples.len() * 32;
+    }
+}
+
+// Number of words between two rank samples
+pub const RANK_SAMPLE_WORDS: usize = 8;
+
+/// Number of ones before every RANK_SAMPLE_WORDS-th word of a bit array
+#[derive(Debug, Clone, Default)]
+pub struct Rank {
+    pub samples: Vec<u32>,
+}
+
+impl Rank {
+    pub fn new(bits: &BitVec) -> Rank {
+        let mut samples = Vec::with_capacity(bits.words().len() / RANK_SAMPLE_WORDS + 1);
+        let mut count = 0;
+
+        for (i, word) in bits.words().iter().enumerate() {
+            if i % RANK_SAMPLE_WORDS == 0 {
+                samples.push(count);
+            }
+
+            count += word.count_ones();
+        }
+
+        return Rank { samples };
+    }
+
+    /// Number of ones before pos
+    pub fn rank(&self, bits: &BitVec, pos: usize) -> usize {
+        let word = pos / 64;
+        let first = word - word % RANK_SAMPLE_WORDS;
+
+        let mut count = self.samples[word / RANK_SAMPLE_WORDS] as usize;
+
+        for w in bits.words()[first..word].iter() {
+            count += w.count_ones() as usize;
+        }
+
+        return count + (bits.words()[word] & ((1 << (pos % 64)) - 1)).count_ones() as usize;
+    }
+
+    pub fn size_bits(&self) -> usize {
+        return self.samples.len() * 32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reads back the i-th value, looking up the ones of the high bits from the closest select sample
+    fn decode(ef: &EliasFano, i: usize) -> u64 {
+        let mut pos = ef.samples[i / SELECT_SAMPLE_RATE] as usize;
+        let mut ones = i - i % SELECT_SAMPLE_RATE;
+
+        loop {
+            if ef.upper.get(pos) {
+                if ones == i {
+                    break;
+                }
+
+                ones += 1;
+            }
+
+            pos += 1;
+        }
+
+        let mut low = 0;
+
+        for bit in 0..ef.width {
+            if ef.lower.get(i * ef.width + bit) {
+                low |= 1 << bit;
+            }
+        }
+
+        return (((pos - i) as u64) << ef.width) | low;
+    }
+
+    #[test]
+    fn bitvec_round_trip() {
+        let mut bits = BitVec::new();
+        bits.push_bits(0b1011, 4);
+        bits.push_unary(3);
+        bits.push_bits(u64::MAX, 64);
+
+        assert_eq!(bits.len(), 72);
+        assert_eq!(bits.words().len(), 2);
+
+        let expected = [true, true, false, true, false, false, false, true];
+
+        for (pos, &bit) in expected.iter().enumerate() {
+            assert_eq!(bits.get(pos), bit, "bit {pos}");
+        }
+
+        assert!((8..72).all(|pos| bits.get(pos)));
+    }
+
+    #[test]
+    fn elias_fano_round_trip() {
+        let sequences: [Vec<u64>; 5] = [
+            vec![0],
+            vec![7],
+            vec![0, 0, 0, 5, 5, 9],
+            (0..1000).map(|i| i * i).collect(),
+            (0..300).map(|i| i / 3).collect(),
+        ];
+
+        for values in sequences.iter() {
+            let ef = EliasFano::new(values);
+
+            assert_eq!(ef.samples.len(), values.len().div_ceil(SELECT_SAMPLE_RATE));
+            assert_eq!(ef.lower.len(), values.len() * ef.width);
+
+            for (i, &value) in values.iter().enumerate() {
+                assert_eq!(decode(&ef, i), value, "value {i} of {values:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn elias_fano_empty() {
+        let ef = EliasFano::new(&[]);
+
+        assert_eq!(ef.width, 0);
+        assert!(ef.lower.is_empty());
+        assert!(ef.samples.is_empty());
+        assert_eq!(ef.upper.len(), 1);
+        assert!(!ef.upper.get(0));
+    }
+
+    #[test]
+    fn rank_at_word_boundaries() {
+        let len = 64 * RANK_SAMPLE_WORDS * 3 + 10;
+        let mut bits = BitVec::with_len(len);
+
+        for pos in (0..len).filter(|pos| pos % 3 == 0 || pos % 64 == 63) {
+            bits.set(pos);
+        }
+
+        let rank = Rank::new(&bits);
+
+        assert_eq!(rank.samples.len(), 4);
+
+        let boundary = 64 * RANK_SAMPLE_WORDS;
+        let positions = [0, 1, 63, 64, 65, 127, 128, boundary - 1, boundary, boundary + 1, 2 * boundary, len - 1];
+
+        for pos in positions {
+            let expected = (0..pos).filter(|&p| bits.get(p)).count();
+            assert_eq!(rank.rank(&bits, pos), expected, "rank at {pos}");
+        }
+    }
+
+    #[test]
+    fn rank_single_bit() {
+        let mut bits = BitVec::new();
+        bits.push(true);
+
+        let rank = Rank::new(&bits);
+
+        assert_eq!(rank.samples, vec![0]);
+        assert_eq!(rank.rank(&bits, 0), 0);
+    }
+
+    #[test]
+    fn rank_empty() {
+        let rank = Rank::new(&BitVec::new());
+
+        assert!(rank.samples.is_empty());
+        assert_eq!(rank.size_bits(), 0);
+    }
+}
@@ -0,0 +1,78 @@
+use super::bdz::{self, Bdz};
+use super::hash::FOHash;
+
+// https://cmph.sourceforge.net/papers/chm92.pdf
+
+/// Order-preserving table over the 3-uniform hypergraph of BDZ tables: every vertex stores a
+/// value in [0, m[, and the values of the three vertices of a key sum to its index modulo m
+#[derive(Debug, Clone, Default)]
+pub struct Chm {
+    /// Seed of the first-order hash of each of the three parts of the vertices
+    pub vertex_seeds: Vec<u64>,
+    /// Number of vertices of each part
+    pub part_size: usize,
+    /// Value of every vertex, 0 for the vertices that are not assigned to any key
+    pub g: Vec<u64>,
+    /// Number of keys, the modulus of the sums of values
+    pub m: usize,
+    pub attempts: u64,
+}
+
+impl Chm {
+    /// Builds the hypergraph of the fingerprints of the keys, which must be distinct, so that the
+    /// i-th fingerprint gets indices[i]. Returns the number of attempts if no attempt gave a
+    /// peelable hypergraph
+    pub fn new(fingerprints: &[u64], indices: &[usize], fo_hash: &FOHash, stage_seed: u64) -> Result<Chm, u64> {
+        let m = fingerprints.len();
+        let part_size = Bdz::part_size(m);
+
+        for attempt in 0..bdz::MAX_ATTEMPTS {
+            let vertex_seeds = Bdz::vertex_seeds(fo_hash, stage_seed, attempt);
+
+            let edges: Vec<[usize; 3]> = fingerprints
+                .iter()
+                .map(|&fingerprint| Bdz::edge(fo_hash, fingerprint, &vertex_seeds, part_size))
+                .collect();
+
+            let Some(order) = Bdz::peel(&edges, 3 * part_size) else {
+                continue;
+            };
+
+            let mut g = vec![0u64; 3 * part_size];
+
+            // Same as BDZ, but the free vertex gets the value completing the index of the key
+            for &(edge, free) in order.iter().rev() {
+                let sum = edges[edge].iter().filter(|&&v| v != free).map(|&v| g[v]).sum::<u64>() % m as u64;
+
+                g[free] = (indices[edge] as u64 + m as u64 - sum) % m as u64;
+            }
+
+            return Ok(Chm {
+                vertex_seeds,
+                part_size,
+                g,
+                m,
+                attempts: attempt + 1,
+            });
+        }
+
+        return Err(bdz::MAX_ATTEMPTS);
+    }
+
+    /// Index of a fingerprint, any index in [0, m[ for the fingerprints of other keys
+    pub fn index(&self, fo_hash: &FOHash, fingerprint: u64) -> usize {
+        let vertices = Bdz::edge(fo_hash, fingerprint, &self.vertex_seeds, self.part_size);
+
+        return (vertices.iter().map(|&v| self.g[v]).sum::<u64>() % self.m as u64) as usize;
+    }
+
+    /// Number of bits of each value
+    pub fn value_bits(&self) -> u32 {
+        return (u64::BITS - (self.m.saturating_sub(1) as u64).leading_zeros()).max(1);
+    }
+
+    /// Size of the values in bits
+    pub fn size_bits(&self) -> usize {
+        return self.g.len() * self.value_bits() as usize;
+    }
+}
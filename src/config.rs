@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_CONFIG_FILE: &str = "pho.json";
+
+/// Input file, or input files and glob patterns merged into one keyset
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Files {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Files {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            Files::One(file) => return vec![file],
+            Files::Many(files) => return files,
+        }
+    }
+}
+
+/// Project configuration, stored as json next to the keyset (pho.json by default).
+/// Every field is optional, command line arguments take precedence over it.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Config {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<Files>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_type: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_format: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_encoding: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalize: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strip_prefix: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub escape: Option<char>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_column: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value_column: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_order_hash: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub second_order_hash: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bucket_size: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_factor: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leaf_size: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fanout: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gamma: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_size: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint_bits: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_attempts_per_bucket: Option<u64>,
+
+    /// In seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl Config {
+    /// Loads the config at the given path, returns an empty config if the file does not exist
+    pub fn load(path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read config file {}: {}", path.display(), e))?;
+
+        return serde_json::from_str(&content)
+            .map_err(|e| format!("Cannot parse config file {}: {}", path.display(), e).into());
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let content = serde_json::to_string_pretty(self)?;
+
+        std::fs::write(path, content + "\n")
+            .map_err(|e| format!("Cannot write config file {}: {}", path.display(), e))?;
+
+        return Ok(());
+    }
+}
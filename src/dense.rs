@@ -0,0 +1,171 @@
+use super::phash::ItemType;
+
+// Two consecutive keys of a range are at most that far apart, so that at least half of the
+// slots of its direct-index table hold a key
+const MAX_GAP: i128 = 2;
+
+// Below that many keys, a range is cheaper to hash than to check on every lookup
+pub const MIN_RANGE_KEYS: usize = 16;
+
+// Ranges are checked one after the other before hashing the key, only the largest ones are kept
+pub const MAX_RANGES: usize = 8;
+
+/// Run of integer keys served by a direct-index table
+#[derive(Debug, Clone)]
+pub struct Range {
+    /// Smallest key of the range, which is the first slot of its table
+    pub start: i128,
+    /// Number of slots of its table, from start to the largest key of the range
+    pub size: usize,
+    /// First slot of its table in the slots of all the ranges
+    pub offset: usize,
+    /// Number of keys of the range
+    pub keys: usize,
+}
+
+/// Dense ranges of an integer keyset: the slot of a key is its offset from the start of its
+/// range, only the keys of the other ranges need a perfect hash
+#[derive(Debug, Clone, Default)]
+pub struct DenseRanges {
+    pub ranges: Vec<Range>,
+    /// Tables of all the ranges, one after the other. Empty slots hold the first key of their
+    /// range, so that they never match the looked up key
+    pub slots: Vec<ItemType>,
+}
+
+impl DenseRanges {
+    /// Splits the keys between the dense ranges and the sparse remainder, in input order.
+    /// Returns None for string keys or when no range is found
+    pub fn split(keys: Vec<ItemType>) -> (Option<DenseRanges>, Vec<ItemType>) {
+        let mut integers: Vec<i128> = keys.iter().filter_map(|key| key.as_integer()).collect();
+
+        if integers.len() < keys.len() {
+            return (None, keys);
+        }
+
+        integers.sort_unstable();
+        integers.dedup();
+
+        // Runs of keys without a gap larger than MAX_GAP, largest first
+        let mut runs: Vec<&[i128]> = integers
+            .chunk_by(|a, b| b - a <= MAX_GAP)
+            .filter(|run| run.len() >= MIN_RANGE_KEYS)
+            .collect();
+
+        if runs.is_empty() {
+            return (None, keys);
+        }
+
+        runs.sort_by_key(|run| std::cmp::Reverse(run.len()));
+        runs.truncate(MAX_RANGES);
+        runs.sort_by_key(|run| run[0]);
+
+        let mut dense = DenseRanges::default();
+
+        for run in runs {
+            let start = run[0];
+            let size = (run[run.len() - 1] - start + 1) as usize;
+            let first = keys.iter().find(|key| key.as_integer() == Some(start)).expect("The first key of a range is a key");
+
+            let offset = dense.slots.len();
+            dense.slots.resize(offset + size, first.clone());
+
+            for &value in run.iter() {
+                dense.slots[offset + (value - start) as usize] = first.with_integer(value);
+            }
+
+            dense.ranges.push(Range {
+                start,
+                size,
+                offset,
+                keys: run.len(),
+            });
+        }
+
+        let sparse = keys.into_iter().filter(|key| dense.range_of(key).is_none()).collect();
+
+        return (Some(dense), sparse);
+    }
+
+    /// Range holding a key, if any
+    pub fn range_of(&self, key: &ItemType) -> Option<&Range> {
+        let value = key.as_integer()?;
+
+        return self
+            .ranges
+            .iter()
+            .find(|range| value >= range.start && value - range.start < range.size as i128);
+    }
+
+    /// Number of distinct keys served by the direct-index tables
+    pub fn num_keys(&self) -> usize {
+        return self.ranges.iter().map(|range| range.keys).sum();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(values: impl IntoIterator<Item = i64>) -> Vec<ItemType> {
+        return values.into_iter().map(ItemType::I64).collect();
+    }
+
+    #[test]
+    fn dense_runs_get_tables_and_the_other_keys_are_hashed() {
+        let input = keys([7, -50].into_iter().chain(1000..2000).chain(5000..5101).chain([123_456]));
+        let (dense, sparse) = DenseRanges::split(input);
+        let dense = dense.expect("Two dense ranges");
+
+        let ranges: Vec<(i128, usize, usize, usize)> =
+            dense.ranges.iter().map(|range| (range.start, range.size, range.offset, range.keys)).collect();
+
+        assert_eq!(ranges, [(1000, 1000, 0, 1000), (5000, 101, 1000, 101)]);
+        assert_eq!(dense.num_keys(), 1101);
+        assert_eq!(dense.slots.len(), 1101);
+        assert_eq!(dense.slots[1000], ItemType::I64(5000));
+        assert_eq!(sparse, keys([7, -50, 123_456]));
+
+        assert_eq!(dense.range_of(&ItemType::I64(1999)).map(|range| range.start), Some(1000));
+        assert!(dense.range_of(&ItemType::I64(2000)).is_none());
+    }
+
+    #[test]
+    fn empty_slots_of_a_range_hold_its_first_key() {
+        let (dense, sparse) = DenseRanges::split(keys((0..40).step_by(2)));
+        let dense = dense.expect("One dense range");
+
+        assert_eq!(dense.ranges.len(), 1);
+        assert_eq!(dense.slots.len(), 39);
+        assert_eq!(dense.slots[1], ItemType::I64(0));
+        assert_eq!(dense.slots[38], ItemType::I64(38));
+        assert!(sparse.is_empty());
+    }
+
+    #[test]
+    fn sparse_keysets_are_only_hashed() {
+        let input = keys((0..100).map(|i| i * 1000));
+        let (dense, sparse) = DenseRanges::split(input.clone());
+        assert!(dense.is_none());
+        assert_eq!(sparse, input);
+
+        // Too short to be worth a range check
+        let (dense, _) = DenseRanges::split(keys(0..MIN_RANGE_KEYS as i64 - 1));
+        assert!(dense.is_none());
+
+        let strings = vec![ItemType::Str("1".to_string()), ItemType::Str("2".to_string())];
+        assert!(DenseRanges::split(strings).0.is_none());
+    }
+
+    #[test]
+    fn only_the_largest_ranges_are_kept() {
+        // Runs of 20 to 29 keys, a thousand apart
+        let input = keys((0..10).flat_map(|i| (0..20 + i).map(move |j| i * 1000 + j)));
+        let (dense, sparse) = DenseRanges::split(input);
+        let dense = dense.expect("Dense ranges");
+
+        let starts: Vec<i128> = dense.ranges.iter().map(|range| range.start).collect();
+        assert_eq!(starts, [2000, 3000, 4000, 5000, 6000, 7000, 8000, 9000]);
+        assert_eq!(sparse.len(), 20 + 21);
+    }
+}
@@ -0,0 +1,46 @@
+// https://dl.acm.org/doi/10.1145/828.1884
+
+/// Second-level tables of the buckets, each one with as many slots as the square of the
+/// number of keys of its bucket, so that a random seed places them with probability above 1/2
+#[derive(Debug, Clone, Default)]
+pub struct Fks {
+    /// First slot of the table of each bucket
+    pub offsets: Vec<u32>,
+    /// Number of slots of the table of each bucket. Empty buckets point to a single slot of
+    /// another table, so that the lookup never divides by zero
+    pub sizes: Vec<u32>,
+    /// Number of slots of all the tables
+    pub table_size: usize,
+}
+
+impl Fks {
+    /// Lays out the tables of buckets of the given number of keys, one after the other
+    pub fn new(bucket_sizes: impl Iterator<Item = usize>) -> Fks {
+        let mut fks = Fks::default();
+        let mut offset = 0;
+
+        for keys in bucket_sizes {
+            if keys == 0 {
+                fks.offsets.push(0);
+                fks.sizes.push(1);
+                continue;
+            }
+
+            fks.offsets.push(offset);
+            fks.sizes.push((keys * keys) as u32);
+
+            offset += (keys * keys) as u32;
+        }
+
+        fks.table_size = offset as usize;
+
+        return fks;
+    }
+
+    /// Size of the offsets and sizes in bits, in the smallest integer type holding them
+    pub fn size_bits(&self) -> usize {
+        let bits = |values: &Vec<u32>| (u32::BITS - values.iter().max().unwrap_or(&0).leading_zeros()).next_power_of_two().max(8) as usize;
+
+        return self.offsets.len() * bits(&self.offsets) + self.sizes.len() * bits(&self.sizes);
+    }
+}
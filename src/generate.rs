@@ -1,17 +1,318 @@
+use super::bits;
 use super::lang::OutputLang;
-use super::phash::PHash;
+use super::hash::FOHash;
+use super::hash::HashSeed;
+use super::gperf::Gperf;
+use super::hash::Hashable;
+use super::normalize::Case;
+use super::phash::{Algorithm, ItemType, PHash};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufWriter;
 use std::io::Write;
 use std::path::PathBuf;
+use std::str::FromStr;
 use strfmt::strfmt;
 
+// Beyond that many keys, the code of a linear scan or a switch grows too large to be picked
+// automatically, whatever its estimated cost
+const LINEAR_MAX_KEYS: usize = 8;
+const SWITCH_MAX_KEYS: usize = 512;
+
+// Rough number of operations of the parts of a lookup, in the unit of `FOHash::cost`
+const MEMORY_ACCESS_COST: f64 = 4.0;
+const BRANCH_COST: f64 = 2.0;
+const SWITCH_COST: f64 = 3.0;
+
+// Comparing two different strings usually stops at their first bytes
+const MISMATCH_COST: f64 = 3.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// No keys, the lookup always misses
+    Empty,
+    /// Compares the key against every value
+    Linear,
+    /// Switch on the length and first byte of string keys, or on integer keys, then compares
+    /// the key against the values of its case
+    Switch,
+    /// Binary search in the sorted values
+    BinarySearch,
+    /// Two-level perfect hash
+    PerfectHash,
+}
+
+impl FromStr for Strategy {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Strategy::Linear),
+            "switch" => Ok(Strategy::Switch),
+            "bsearch" => Ok(Strategy::BinarySearch),
+            "phash" => Ok(Strategy::PerfectHash),
+            _ => Err("Cannot find a corresponding lookup strategy. Expected: auto, phash, switch, bsearch, linear"),
+        }
+    }
+}
+
+impl Strategy {
+    /// Cheapest strategy for the keys of the table, among the ones whose code stays small
+    pub fn from_phash(phash: &PHash) -> Strategy {
+        // Retrieval tables do not store the keys, there is nothing to compare against
+        if phash.retrieval().is_some() {
+            return Strategy::PerfectHash;
+        }
+
+        if phash.m() == 0 {
+            return Strategy::Empty;
+        }
+
+        return [Strategy::Linear, Strategy::Switch, Strategy::BinarySearch, Strategy::PerfectHash]
+            .into_iter()
+            .filter(|strategy| match strategy {
+                Strategy::Linear => phash.m() <= LINEAR_MAX_KEYS,
+                Strategy::Switch => phash.m() <= SWITCH_MAX_KEYS,
+                _ => true,
+            })
+            .min_by(|a, b| a.cost(phash).total_cmp(&b.cost(phash)))
+            .unwrap_or(Strategy::PerfectHash);
+    }
+
+    /// Strategy requested on the command line or in the project config. Tables of an explicitly
+    /// requested algorithm are looked up by perfect hash, unless another strategy is requested
+    pub fn requested(name: Option<&str>, explicit_algorithm: bool) -> Result<Option<Strategy>, &'static str> {
+        return match name {
+            Some("auto") => Ok(None),
+            Some(name) => Ok(Some(name.parse()?)),
+            None if explicit_algorithm => Ok(Some(Strategy::PerfectHash)),
+            None => Ok(None),
+        };
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Strategy::Empty => "empty",
+            Strategy::Linear => "linear",
+            Strategy::Switch => "switch",
+            Strategy::BinarySearch => "bsearch",
+            Strategy::PerfectHash => "phash",
+        }
+    }
+
+    /// Rough number of operations of a lookup of the keys of the table
+    pub fn cost(&self, phash: &PHash) -> f64 {
+        let items = phash.items();
+        let m = items.len().max(1) as f64;
+
+        let is_string = matches!(items.first().map(|item| item.item_type()), Some(ItemType::Str(_) | ItemType::Bytes(_)));
+        let avg_len = items.iter().map(|item| item.item_type().hashable().len()).sum::<usize>() as f64 / m;
+
+        // Comparing the key to the value it matches, and to other values
+        let (compare, mismatch) = if is_string { (2.0 + avg_len, MISMATCH_COST) } else { (1.0, 1.0) };
+        let probes = (m + 1.0).log2().ceil();
+
+        match self {
+            Strategy::Empty => 0.0,
+            Strategy::Linear => m * (mismatch + BRANCH_COST) + compare,
+            Strategy::Switch if is_string => {
+                // Expected number of values sharing the case of a key
+                let mut cases: HashMap<(usize, u8), usize> = HashMap::new();
+
+                for item in items.iter() {
+                    let bytes = item.item_type().hashable();
+                    *cases.entry((bytes.len(), bytes.first().copied().unwrap_or(0))).or_default() += 1;
+                }
+
+                let per_case = cases.values().map(|&n| (n * n) as f64).sum::<f64>() / m;
+
+                avg_len + 2.0 * SWITCH_COST + per_case * (mismatch + BRANCH_COST) + compare
+            }
+            // Compilers turn sparse switches into decision trees
+            Strategy::Switch => SWITCH_COST + probes * BRANCH_COST + compare,
+            Strategy::BinarySearch => probes * (mismatch + BRANCH_COST + MEMORY_ACCESS_COST) + compare,
+            Strategy::PerfectHash => {
+                let accesses = match phash.algorithm() {
+                    Algorithm::Chd | Algorithm::PTHash | Algorithm::KPerfect => 2.0,
+                    Algorithm::BBHash | Algorithm::Chm | Algorithm::Retrieval | Algorithm::Fks => 4.0,
+                    Algorithm::Bdz => 5.0,
+                    Algorithm::Monotone => 8.0,
+                    Algorithm::RecSplit => 10.0,
+                };
+
+                let so_cost = if phash.algorithm().uses_second_order_hash() { phash.so_hash().cost() } else { 0.0 };
+
+                phash.fo_hash().cost(avg_len) + so_cost + accesses * MEMORY_ACCESS_COST + compare
+            }
+        }
+    }
+}
+
+// Cases of a switch lookup: in C, nested switches on the length and first byte of string keys, or
+// a switch on integer keys, each case comparing the key to its values. In Python, the indices of
+// the values of each case, looked up in a dict. C cases return the hit of the index of the value
+fn switch_cases(
+    output_lang: &OutputLang,
+    keys: &[&ItemType],
+    values_name: &str,
+    key_suffix: &str,
+    ignore_case: Option<&str>,
+    hit: &dyn Fn(usize) -> String,
+) -> String {
+    let mut cases: Vec<(Vec<i128>, Vec<usize>)> = Vec::new();
+
+    for (i, key) in keys.iter().enumerate() {
+        let case = match (output_lang, key) {
+            (OutputLang::C, ItemType::Str(s)) => vec![s.len() as i128, s.bytes().next().unwrap_or(0) as i128],
+            (OutputLang::Python, ItemType::Str(s)) => vec![s.chars().count() as i128, s.chars().next().map_or(0, |c| c as i128)],
+            (_, ItemType::Bytes(b)) => vec![b.len() as i128, b.first().copied().unwrap_or(0) as i128],
+            _ => vec![key.as_integer().unwrap_or(0)],
+        };
+
+        match cases.iter_mut().find(|(other, _)| *other == case) {
+            Some((_, indices)) => indices.push(i),
+            None => cases.push((case, vec![i])),
+        }
+    }
+
+    cases.sort();
+
+    let mut code = String::new();
+
+    match output_lang {
+        OutputLang::Python => {
+            for (case, indices) in cases.iter() {
+                let case = case.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(", ");
+                let indices = indices.iter().map(|i| format!("{i}, ")).collect::<String>();
+
+                code += &format!("    ({case}): ({indices}),\n");
+            }
+        }
+        OutputLang::C if cases.first().is_some_and(|(case, _)| case.len() == 1) => {
+            code += "    switch(key)\n    {\n";
+
+            for (_, indices) in cases.iter() {
+                code += &format!("        case {}:\n            return {};\n", output_lang.get_key_literal(keys[indices[0]]), hit(indices[0]));
+            }
+
+            code += "    }\n";
+        }
+        OutputLang::C => {
+            code += &format!("    switch({})\n    {{\n", output_lang.get_key_size(keys[0], "key"));
+
+            for (j, (case, indices)) in cases.iter().enumerate() {
+                let len = case[0];
+
+                // The empty key has no first byte to read
+                if len == 0 {
+                    code += &format!("        case 0:\n            return {};\n", hit(indices[0]));
+                    continue;
+                }
+
+                if j == 0 || cases[j - 1].0[0] != len {
+                    code += &format!("        case {len}:\n            switch(((const unsigned char*)key)[0])\n            {{\n");
+                }
+
+                code += &format!("                case {}:\n", case[1]);
+
+                for &i in indices.iter() {
+                    let equals = match ignore_case {
+                        Some(ignore_case) => format!("{ignore_case}(key, {values_name}[{i}]{key_suffix}) == 0"),
+                        None => format!("memcmp(key, {values_name}[{i}]{key_suffix}, {len}) == 0"),
+                    };
+
+                    code += &format!(
+                        "                    if({equals})\n                    {{\n                        return {};\n                    }}\n",
+                        hit(i)
+                    );
+                }
+
+                code += "                    break;\n";
+
+                if j == cases.len() - 1 || cases[j + 1].0[0] != len {
+                    code += "            }\n            break;\n";
+                }
+            }
+
+            code += "    }\n";
+        }
+    }
+
+    return code;
+}
+
+// Values of the lookups comparing the keys, and the slots of the values in sorted order when the
+// values are not sorted. Switches and binary searches look the values up in sorted order, except
+// for order-preserving tables whose values stay in slot order
+fn compared_keys(phash: &PHash, strategy: Strategy) -> (Vec<&ItemType>, Option<Vec<usize>>) {
+    let keys: Vec<&ItemType> = phash.items().iter().map(|item| item.item_type()).collect();
+
+    if strategy == Strategy::Linear {
+        return (keys, None);
+    }
+
+    let mut sorted_slots: Vec<usize> = (0..keys.len()).collect();
+    sorted_slots.sort_by_key(|&slot| (keys[slot].as_integer(), keys[slot].hashable()));
+
+    if sorted_slots.iter().enumerate().all(|(i, &slot)| i == slot) {
+        return (keys, None);
+    }
+
+    if !phash.algorithm().preserves_order() {
+        return (sorted_slots.iter().map(|&slot| keys[slot]).collect(), None);
+    }
+
+    if strategy != Strategy::BinarySearch {
+        return (keys, None);
+    }
+
+    return (keys, Some(sorted_slots));
+}
+
+fn write_array<W: Write>(
+    output_buffer: &mut W,
+    output_lang: &OutputLang,
+    vars: &HashMap<String, String>,
+    values: impl Iterator<Item = String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    write!(
+        output_buffer,
+        "{} = {}",
+        strfmt(output_lang.get_array_decl(), vars).unwrap(),
+        output_lang.get_array_start()
+    )?;
+    writeln!(output_buffer)?;
+
+    for value in values {
+        write!(output_buffer, "    {}{}", value, output_lang.get_array_sep())?;
+        writeln!(output_buffer)?;
+    }
+
+    write!(
+        output_buffer,
+        "{}{}",
+        output_lang.get_array_end(),
+        output_lang.get_line_end()
+    )?;
+    writeln!(output_buffer)?;
+    writeln!(output_buffer)?;
+
+    return Ok(());
+}
+
+// Number of bits of the largest pilot
+fn pilot_bits(phash: &PHash) -> u32 {
+    let max_pilot = phash.buckets().iter().map(|bucket| bucket.pilot()).max().unwrap_or(0);
+    return u64::BITS - max_pilot.leading_zeros();
+}
+
 pub fn gen_code(
     output_path: PathBuf,
     phash: &PHash,
     name: &str,
     namespace: &str,
+    fingerprint_bits: Option<u32>,
+    requested_strategy: Option<Strategy>,
     add_test_code: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let ext = output_path
@@ -21,223 +322,1500 @@ pub fn gen_code(
 
     let output_lang: OutputLang = ext.into();
 
+    // C strings are null-terminated, their size is the position of the first null byte. Byte
+    // strings are passed with their size
+    if matches!(output_lang, OutputLang::C) {
+        let null_key = phash
+            .items()
+            .iter()
+            .map(|item| item.item_type())
+            .find(|key| matches!(key, ItemType::Str(_)) && key.hashable().contains(&0));
+
+        if let Some(key) = null_key {
+            return Err(format!("Key {key} holds a null byte, C strings cannot hold them, use --key-type bytes").into());
+        }
+    }
+
+    let normalization = phash.normalization();
+
+    if let Some(form) = normalization.form
+        && matches!(output_lang, OutputLang::C)
+    {
+        return Err(format!("C lookups cannot normalize the keys to {}, only Python ones can", form.name()).into());
+    }
+
+    // Fingerprints are only compared after hashing the key, even for a handful of keys, and
+    // retrieval tables have no key to compare
+    let needs_phash = phash.retrieval().is_some() || (fingerprint_bits.is_some() && phash.m() > 0);
+
+    let strategy = match requested_strategy {
+        Some(strategy) if needs_phash && strategy != Strategy::PerfectHash => {
+            return Err(format!(
+                "Cannot use the {} lookup strategy, fingerprint and retrieval tables are only looked up by perfect hash",
+                strategy.name()
+            )
+            .into());
+        }
+        Some(_) if phash.m() == 0 => Strategy::Empty,
+        Some(strategy) => strategy,
+        None if needs_phash => Strategy::PerfectHash,
+        None => Strategy::from_phash(phash),
+    };
+
+    if !needs_phash && phash.m() > 0 {
+        let costs: Vec<String> = [Strategy::Linear, Strategy::Switch, Strategy::BinarySearch, Strategy::PerfectHash]
+            .iter()
+            .map(|strategy| format!("{} {:.1}", strategy.name(), strategy.cost(phash)))
+            .collect();
+
+        println!("Estimated lookup costs: {}", costs.join(", "));
+    }
+
+    // Dense ranges of integer keys are checked before the lookup of the other keys
+    let strategy_name = match phash.dense() {
+        Some(_) if strategy == Strategy::Empty => "direct".to_string(),
+        Some(_) => format!("direct + {}", strategy.name()),
+        None => strategy.name().to_string(),
+    };
+
     println!(
-        "Generating {:?} code to file: {}",
+        "Generating {:?} code to file: {} (lookup strategy: {})",
         output_lang,
-        output_path.display()
+        output_path.display(),
+        strategy_name
     );
 
+    // Lookups in k-perfect tables compare the fingerprints of a whole group
+    let false_positive_rate = fingerprint_bits.map(|bits| phash.group_size() as f64 / (1u64 << bits) as f64);
+
+    if let (Some(bits), Some(rate)) = (fingerprint_bits, false_positive_rate) {
+        println!(
+            "Storing {bits}-bits fingerprints instead of the keys, false-positive rate: {:.6}% (1 in {:.0})",
+            100.0 * rate,
+            1.0 / rate
+        );
+    }
+
     let output_file = File::create(&output_path).expect("Cannot create output file");
     let mut output_buffer = BufWriter::new(output_file);
 
     let cm_st = output_lang.get_comment_start();
     let cm_end = output_lang.get_comment_end();
 
-    write!(
+    writeln!(
         output_buffer,
-        "{cm_st} This file has been auto-generated by pho {cm_end}\n"
+        "{cm_st} This file has been auto-generated by pho {cm_end}"
     )?;
-    write!(
+    writeln!(
         output_buffer,
-        "{cm_st} Check out https://github.com/romainaugier/pho for more information {cm_end}\n"
+        "{cm_st} Check out https://github.com/romainaugier/pho for more information {cm_end}"
     )?;
-    write!(output_buffer, "\n")?;
+    writeln!(output_buffer)?;
+
+    // Metadata
+
+    let mut metadata = vec![
+        format!("Keys: {}", phash.m() + phash.dense().map_or(0, |dense| dense.num_keys())),
+        format!("Lookup strategy: {}", strategy_name),
+    ];
+
+    if let Some(values) = phash.values()
+        && fingerprint_bits.is_none()
+    {
+        metadata.push(format!("Values: {}", values.value_type.name()));
+    }
+
+    if !normalization.is_empty() {
+        metadata.push(format!("Key normalization: {}", normalization.describe()));
+    }
+
+    if let Some(dense) = phash.dense() {
+        metadata.push(format!(
+            "Direct-index ranges: {} ({} keys in {} slots)",
+            dense.ranges.len(),
+            dense.num_keys(),
+            dense.slots.len()
+        ));
+    }
+
+    if strategy == Strategy::PerfectHash {
+        metadata.push(format!("Algorithm: {}", phash.algorithm().name()));
+        metadata.push(format!("First-order hash: {}", phash.fo_hash().name()));
+
+        if let FOHash::Positions(positions) = phash.fo_hash() {
+            metadata.push(format!("Key positions: {}", positions.describe()));
+        }
+
+        if let Some(bbhash) = phash.bbhash() {
+            metadata.push(format!("Levels: {} (gamma {})", bbhash.num_levels(), bbhash.gamma));
+            metadata.push(format!(
+                "Bit arrays: {:.2} bits/key",
+                bbhash.size_bits() as f64 / phash.m() as f64
+            ));
+        } else if let Some(bdz) = phash.bdz() {
+            metadata.push(format!("Vertices: 3 x {} ({} attempt(s))", bdz.part_size, bdz.attempts));
+            metadata.push(format!(
+                "g-values: {:.2} bits/key",
+                bdz.size_bits() as f64 / phash.m() as f64
+            ));
+        } else if let Some(chm) = phash.chm() {
+            metadata.push(format!("Vertices: 3 x {} ({} attempt(s))", chm.part_size, chm.attempts));
+            metadata.push(format!(
+                "Values: {} bits, {:.2} bits/key, keys in input order",
+                chm.value_bits(),
+                chm.size_bits() as f64 / phash.m() as f64
+            ));
+        } else if let Some(monotone) = phash.monotone() {
+            metadata.push(format!("Buckets: {} of {} keys", monotone.num_buckets, phash.bucket_size()));
+            metadata.push(format!(
+                "Functions: {:.2} bits/key, keys in lexicographic order",
+                monotone.size_bits() as f64 / phash.m() as f64
+            ));
+        } else if let Some(retrieval) = phash.retrieval() {
+            metadata.push(format!(
+                "Values: {} bits, {:.2} bits/key, keys are not stored",
+                retrieval.width,
+                retrieval.size_bits() as f64 / phash.m().max(1) as f64
+            ));
+        } else {
+            metadata.push(format!("Second-order hash: {}", phash.so_hash().name()));
+            metadata.push(format!("Buckets: {}", phash.buckets().len()));
+            metadata.push(format!("Average bucket size (lambda): {}", phash.bucket_size()));
+
+            if phash.algorithm() == Algorithm::KPerfect {
+                metadata.push(format!(
+                    "Groups: {} of {} slots",
+                    phash.table_size() / phash.group_size(),
+                    phash.group_size()
+                ));
+            }
+        }
+
+        metadata.push(format!("Seed: {}", phash.seed()));
+
+        if let Some(recsplit) = phash.recsplit() {
+            metadata.push(format!(
+                "Leaf size: {}, fanout: {}",
+                recsplit.splits.leaf_size, recsplit.splits.fanout
+            ));
+            metadata.push(format!(
+                "Splitting trees: {:.2} bits/key",
+                recsplit.size_bits() as f64 / phash.m() as f64
+            ));
+        }
+
+        if phash.algorithm() == Algorithm::PTHash {
+            metadata.push(format!("Pilots type: {}", output_lang.map_uint(pilot_bits(phash))));
+        }
+
+        if phash.range() > phash.table_size() {
+            metadata.push(format!(
+                "Load factor: {:.2}, {} slots remapped",
+                phash.m() as f64 / phash.range() as f64,
+                phash.remap().len()
+            ));
+        }
+
+        if let (Some(bits), Some(rate)) = (fingerprint_bits, false_positive_rate) {
+            metadata.push(format!("Fingerprints: {bits} bits, false-positive rate {:.6}%", 100.0 * rate));
+        }
+
+        if phash.table_size() > phash.m() {
+            metadata.push(format!(
+                "Non-minimal table: {} slots (load factor {:.2})",
+                phash.table_size(),
+                phash.m() as f64 / phash.table_size() as f64
+            ));
+        }
+    }
+
+    for line in metadata.iter() {
+        writeln!(output_buffer, "{cm_st} {line} {cm_end}")?;
+    }
+
+    writeln!(output_buffer)?;
 
     let mut vars = HashMap::new();
 
-    let first_item = phash
+    // Without any item, the key type defaults to a string
+    let item_type = phash
         .first_item()
-        .unwrap_or_else(|| panic!("Cannot find any item"));
+        .map(|item| item.item_type().clone())
+        .or_else(|| phash.dense().map(|dense| dense.slots[0].clone()))
+        .unwrap_or_default();
+
+    // Keys are looked up as their entry when gperf keywords are declared with a struct, when the
+    // keys have values, or when they are C byte strings stored with their size. The values of the
+    // tables then point to the entry of their key
+    let has_entries = fingerprint_bits.is_none() && phash.retrieval().is_none();
+    let gperf_entries = phash
+        .gperf()
+        .filter(|gperf| has_entries && gperf.struct_name.is_some() && matches!(item_type, ItemType::Str(_)));
+    let value_entries = phash.values().filter(|_| has_entries);
 
-    let first_bucket = phash
-        .first_bucket()
-        .unwrap_or_else(|| panic!("Cannot find any bucket"));
+    // With %ignore-case, the entries keep the keywords as written and the lowercased key is
+    // compared to them case-insensitively, unless other transforms change the keywords
+    let case_insensitive = gperf_entries.is_some_and(|gperf| {
+        gperf.ignore_case
+            && gperf.keywords.iter().all(|keyword| {
+                normalization.apply(ItemType::Str(keyword.name.clone())) == Ok(ItemType::Str(keyword.name.to_ascii_lowercase()))
+            })
+    });
+    let size_entries = has_entries && matches!((&output_lang, &item_type), (OutputLang::C, ItemType::Bytes(_)));
+
+    let entries_name = format!("{namespace}_{name}_entries");
+    let mut entry_indices: HashMap<Hashable, usize> = HashMap::new();
+
+    // Struct of the entries and field of their key
+    let entry_struct = match (gperf_entries, value_entries) {
+        (Some(gperf), _) => gperf.struct_name.clone().map(|struct_name| (struct_name, gperf.slot_name.clone())),
+        (None, Some(_)) => Some((format!("{namespace}_{name}_entry"), "key".to_string())),
+        (None, None) if size_entries => Some((format!("{namespace}_{name}_entry"), "key".to_string())),
+        (None, None) => None,
+    };
+
+    let value_type = match &entry_struct {
+        Some((struct_name, _)) => output_lang.get_entry_type(struct_name),
+        None => output_lang.get_type(&item_type).to_string(),
+    };
+    let key_suffix = entry_struct
+        .as_ref()
+        .map_or(String::new(), |(_, slot_name)| output_lang.get_entry_key_suffix(slot_name));
+    let size_suffix = output_lang.get_entry_size_suffix(&item_type);
+
+    // Entries are returned by reference, a miss returns none of them
+    let miss = match entry_struct {
+        Some(_) => output_lang.get_null_literal().to_string(),
+        None => output_lang.get_miss_value(&item_type, "key"),
+    };
 
     let mut imports = Vec::new();
 
-    if let Some(imps) = output_lang.get_imports_from_type(first_item.item_type()) {
+    if let Some(imps) = output_lang.get_imports_from_type(&item_type) {
         imports.extend(imps.lines().map(String::from));
     }
 
-    if add_test_code {
-        if let Some(imps) = output_lang.get_imports_for_test(first_item.item_type()) {
-            imports.extend(imps.lines().map(String::from));
-        }
+    if entry_struct.is_some()
+        && let Some(imps) = output_lang.get_imports_for_entries()
+    {
+        imports.extend(imps.lines().map(String::from));
+    }
+
+    if let Some(imps) = output_lang.get_imports_for_normalization(normalization) {
+        imports.extend(imps.lines().map(String::from));
+    }
+
+    if strategy == Strategy::BinarySearch && matches!(output_lang, OutputLang::Python) {
+        imports.push("import bisect".to_string());
+    }
+
+    if add_test_code
+        && let Some(imps) = output_lang.get_imports_for_test(&item_type)
+    {
+        imports.extend(imps.lines().map(String::from));
     }
 
     for import in imports.iter() {
-        write!(output_buffer, "{}\n", import)?;
+        writeln!(output_buffer, "{}", import)?;
     }
 
-    if imports.len() > 0 {
-        write!(output_buffer, "\n")?;
+    if !imports.is_empty() {
+        writeln!(output_buffer)?;
     }
 
-    // First-order hash function
+    // The code of gperf files is C, copied as gperf does
+    if let Some(gperf) = phash.gperf()
+        && matches!(output_lang, OutputLang::C)
+    {
+        if !gperf.code.is_empty() {
+            writeln!(output_buffer, "{}", gperf.code)?;
+        }
 
-    let fo_hash_name = phash.fo_hash().name();
-    let fo_hash_data = output_lang
-        .get_fo_hash_data(fo_hash_name)
-        .expect("Cannot get the fo hash function data");
-    let fo_function_name = format!("{namespace}_{fo_hash_name}").to_string();
-    vars.insert("name".to_string(), fo_function_name.clone());
+        if let Some(declaration) = gperf.struct_declaration.as_ref()
+            && !gperf.omit_struct_type
+        {
+            writeln!(output_buffer, "{}", declaration)?;
+        }
+    }
 
-    write!(
-        output_buffer,
-        "{}",
-        strfmt(&fo_hash_data.body, &vars).unwrap()
-    )?;
-    write!(output_buffer, "\n")?;
+    if gperf_entries.is_none()
+        && let Some((struct_name, _)) = entry_struct.as_ref()
+        && let Some(declaration) = output_lang.get_values_struct(struct_name, &item_type, value_entries.map(|values| &values.value_type))
+    {
+        writeln!(output_buffer, "{}", declaration)?;
+    }
 
-    // Second-order hash function
+    if let Some((struct_name, _)) = entry_struct.as_ref() {
+        let mut initializers = Vec::new();
 
-    let so_hash_name = phash.so_hash().name();
-    let so_hash_data = output_lang
-        .get_so_hash_data(so_hash_name)
-        .expect("Cannot get the so hash function data");
-    let so_function_name = format!("{namespace}_{so_hash_name}").to_string();
-    vars.insert("name".to_string(), so_function_name.clone());
+        // Duplicate keys are only looked up as their first entry
+        let mut add_entry = |key: &ItemType, initializer: String| {
+            if entry_indices.contains_key(&key.hashable()) {
+                return;
+            }
 
-    write!(
-        output_buffer,
-        "{}",
-        strfmt(&so_hash_data.body, &vars).unwrap()
-    )?;
-    write!(output_buffer, "\n")?;
+            entry_indices.insert(key.hashable(), initializers.len());
+            initializers.push(output_lang.get_entry_literal(&initializer));
+        };
 
-    // Seeds
+        if let Some(gperf) = gperf_entries {
+            for keyword in gperf.keywords.iter() {
+                let key = normalization.apply(ItemType::Str(keyword.name.clone()))?;
+                let literal = match case_insensitive {
+                    true => output_lang.get_string_literal(&keyword.name),
+                    false => output_lang.get_key_literal(&key),
+                };
 
-    let so_seeds_name = format!("{namespace}_{name}_seeds");
-    vars.insert("name".to_string(), so_seeds_name.clone());
-    vars.insert(
-        "type".to_string(),
-        output_lang
-            .map_seed(&first_bucket.so_hash().seed())
-            .to_string(),
-    );
-    vars.insert("size".to_string(), format!("{}", phash.buckets().len()));
+                add_entry(&key, Gperf::initializer(keyword, &literal));
+            }
+        } else if let Some(values) = value_entries {
+            for (key, value) in values.keys.iter().zip(values.values.iter()) {
+                add_entry(key, format!("{}, {}", output_lang.get_key_initializer(key), output_lang.get_value_literal(value, &values.value_type)));
+            }
+        } else {
+            for item in phash.items().iter() {
+                add_entry(item.item_type(), output_lang.get_key_initializer(item.item_type()));
+            }
+        }
 
-    write!(
-        output_buffer,
-        "{} = {}",
-        strfmt(&output_lang.get_array_decl(), &vars).unwrap(),
-        output_lang.get_array_start()
-    )?;
-    write!(output_buffer, "\n")?;
+        vars.insert("name".to_string(), entries_name.clone());
+        vars.insert("type".to_string(), format!("struct {struct_name}"));
+        vars.insert("size".to_string(), format!("{}", initializers.len()));
+
+        write_array(&mut output_buffer, &output_lang, &vars, initializers.into_iter())?;
+    }
+
+    // Values of the tables: the keys, or their entry
+    let value_literal = |key: &ItemType| match entry_indices.get(&key.hashable()) {
+        Some(&index) => output_lang.get_entry_reference(&entries_name, index),
+        None => output_lang.get_key_literal(key),
+    };
+
+    if strategy == Strategy::PerfectHash {
+        // First-order hash function
+
+        let fo_hash_name = phash.fo_hash().name();
+        let fo_hash_data = output_lang
+            .get_fo_hash_data(fo_hash_name)
+            .expect("Cannot get the fo hash function data");
+        let fo_function_name = format!("{namespace}_{fo_hash_name}").to_string();
+        vars.insert("name".to_string(), fo_function_name.clone());
+
+        if let FOHash::Positions(positions) = phash.fo_hash() {
+            let mut mix: String = positions.positions().iter().map(|&position| output_lang.get_position_mix(position)).collect();
+
+            if !mix.is_empty() {
+                mix += "\n";
+            }
+
+            vars.insert("positions".to_string(), mix);
+        }
 
-    for bucket in phash.buckets().iter() {
         write!(
             output_buffer,
-            "    {}{}",
-            bucket.so_hash().seed(),
-            output_lang.get_array_sep()
+            "{}",
+            strfmt(&fo_hash_data.body, &vars).unwrap()
         )?;
-        write!(output_buffer, "\n")?;
-    }
+        writeln!(output_buffer)?;
 
-    write!(
-        output_buffer,
-        "{}{}",
-        output_lang.get_array_end(),
-        output_lang.get_line_end()
-    )?;
-    write!(output_buffer, "\n")?;
-    write!(output_buffer, "\n")?;
+        // Second-order hash function
+
+        let so_hash_name = phash.so_hash().name();
+        let so_function_name = format!("{namespace}_{so_hash_name}").to_string();
+
+        if phash.algorithm().uses_second_order_hash() {
+            let so_hash_data = output_lang
+                .get_so_hash_data(so_hash_name)
+                .expect("Cannot get the so hash function data");
+            vars.insert("name".to_string(), so_function_name.clone());
+
+            write!(
+                output_buffer,
+                "{}",
+                strfmt(&so_hash_data.body, &vars).unwrap()
+            )?;
+            writeln!(output_buffer)?;
+        }
+
+        let first_bucket = phash
+            .first_bucket()
+            .unwrap_or_else(|| panic!("Cannot find any bucket"));
+
+        if phash.algorithm() == Algorithm::PTHash {
+            // Pilots
+
+            let pilots_name = format!("{namespace}_{name}_pilots");
+            vars.insert("name".to_string(), pilots_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(pilot_bits(phash)).to_string());
+            vars.insert("size".to_string(), format!("{}", phash.buckets().len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                phash.buckets().iter().map(|bucket| bucket.pilot().to_string()),
+            )?;
+
+            let num_sparse_buckets = phash.buckets().len() - phash.dense_buckets();
+
+            vars.insert("pilots_name".to_string(), pilots_name);
+            vars.insert("dense_buckets".to_string(), phash.dense_buckets().to_string());
+            vars.insert("sparse_buckets".to_string(), num_sparse_buckets.to_string());
+            vars.insert("range".to_string(), phash.range().to_string());
+            vars.insert("slot".to_string(), "pos".to_string());
+
+            // Remap of the positions past m
+
+            if !phash.remap().is_empty() {
+                let remap_name = format!("{namespace}_{name}_remap");
+                let remap_bits = u64::BITS - (phash.m() as u64).leading_zeros();
+                vars.insert("name".to_string(), remap_name.clone());
+                vars.insert("type".to_string(), output_lang.map_uint(remap_bits).to_string());
+                vars.insert("size".to_string(), format!("{}", phash.remap().len()));
+
+                write_array(
+                    &mut output_buffer,
+                    &output_lang,
+                    &vars,
+                    phash.remap().iter().map(|pos| pos.to_string()),
+                )?;
+
+                vars.insert(
+                    "slot".to_string(),
+                    output_lang.get_ternary(
+                        &format!("pos < {}", phash.m()),
+                        "pos",
+                        &format!("{remap_name}[pos - {}]", phash.m()),
+                    ),
+                );
+            }
+        } else if let Some(bbhash) = phash.bbhash() {
+            // Bit arrays of the levels, packed in a single array of words
+
+            let prefix = format!("{namespace}_{name}");
+            let bits_name = format!("{prefix}_bits");
+            vars.insert("name".to_string(), bits_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(64).to_string());
+            vars.insert("size".to_string(), format!("{}", bbhash.bits.words().len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                bbhash.bits.words().iter().map(|&word| output_lang.get_word_literal(word)),
+            )?;
+
+            let ranks_name = format!("{prefix}_ranks");
+            let ranks_bits = u32::BITS - bbhash.rank.samples.last().unwrap_or(&0).leading_zeros();
+            vars.insert("name".to_string(), ranks_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(ranks_bits).to_string());
+            vars.insert("size".to_string(), format!("{}", bbhash.rank.samples.len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                bbhash.rank.samples.iter().map(|sample| sample.to_string()),
+            )?;
+
+            let level_offsets_name = format!("{prefix}_level_offsets");
+            let offsets_bits = u64::BITS - (*bbhash.level_offsets.last().unwrap_or(&0) as u64).leading_zeros();
+            vars.insert("name".to_string(), level_offsets_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(offsets_bits).to_string());
+            vars.insert("size".to_string(), format!("{}", bbhash.level_offsets.len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                bbhash.level_offsets.iter().map(|offset| offset.to_string()),
+            )?;
+
+            let level_seeds_name = format!("{prefix}_level_seeds");
+            let is_64bits = phash.fo_hash().is_64bits();
+            vars.insert("name".to_string(), level_seeds_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(if is_64bits { 64 } else { 32 }).to_string());
+            vars.insert("size".to_string(), format!("{}", bbhash.num_levels()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                bbhash
+                    .level_seeds
+                    .iter()
+                    .map(|&seed| output_lang.get_seed_literal(seed, is_64bits)),
+            )?;
+
+            vars.insert("prefix".to_string(), prefix);
+            vars.insert("bits_name".to_string(), bits_name);
+            vars.insert("ranks_name".to_string(), ranks_name);
+            vars.insert("level_offsets_name".to_string(), level_offsets_name);
+            vars.insert("level_seeds_name".to_string(), level_seeds_name);
+            vars.insert("num_levels".to_string(), bbhash.num_levels().to_string());
+            vars.insert("rank_sample_words".to_string(), bits::RANK_SAMPLE_WORDS.to_string());
+        } else if let Some(bdz) = phash.bdz() {
+            // 2-bits g-values of the vertices, 32 per word
+
+            let prefix = format!("{namespace}_{name}");
+            let g_name = format!("{prefix}_g");
+            vars.insert("name".to_string(), g_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(64).to_string());
+            vars.insert("size".to_string(), format!("{}", bdz.g.words().len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                bdz.g.words().iter().map(|&word| output_lang.get_word_literal(word)),
+            )?;
+
+            let ranks_name = format!("{prefix}_ranks");
+            let ranks_bits = u32::BITS - bdz.ranks.last().unwrap_or(&0).leading_zeros();
+            vars.insert("name".to_string(), ranks_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(ranks_bits).to_string());
+            vars.insert("size".to_string(), format!("{}", bdz.ranks.len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                bdz.ranks.iter().map(|rank| rank.to_string()),
+            )?;
+
+            let vertex_seeds_name = format!("{prefix}_vertex_seeds");
+            let is_64bits = phash.fo_hash().is_64bits();
+            vars.insert("name".to_string(), vertex_seeds_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(if is_64bits { 64 } else { 32 }).to_string());
+            vars.insert("size".to_string(), format!("{}", bdz.vertex_seeds.len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                bdz.vertex_seeds.iter().map(|&seed| output_lang.get_seed_literal(seed, is_64bits)),
+            )?;
+
+            vars.insert("prefix".to_string(), prefix);
+            vars.insert("g_name".to_string(), g_name);
+            vars.insert("ranks_name".to_string(), ranks_name);
+            vars.insert("vertex_seeds_name".to_string(), vertex_seeds_name);
+            vars.insert("part_size".to_string(), bdz.part_size.to_string());
+            vars.insert("rank_sample_words".to_string(), bits::RANK_SAMPLE_WORDS.to_string());
+        } else if let Some(chm) = phash.chm() {
+            // Values of the vertices, the smallest type holding m - 1
+
+            let g_name = format!("{namespace}_{name}_g");
+            vars.insert("name".to_string(), g_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(chm.value_bits()).to_string());
+            vars.insert("size".to_string(), format!("{}", chm.g.len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                chm.g.iter().map(|value| value.to_string()),
+            )?;
+
+            let vertex_seeds_name = format!("{namespace}_{name}_vertex_seeds");
+            let is_64bits = phash.fo_hash().is_64bits();
+            vars.insert("name".to_string(), vertex_seeds_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(if is_64bits { 64 } else { 32 }).to_string());
+            vars.insert("size".to_string(), format!("{}", chm.vertex_seeds.len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                chm.vertex_seeds.iter().map(|&seed| output_lang.get_seed_literal(seed, is_64bits)),
+            )?;
+
+            vars.insert("g_name".to_string(), g_name);
+            vars.insert("vertex_seeds_name".to_string(), vertex_seeds_name);
+            vars.insert("part_size".to_string(), chm.part_size.to_string());
+        } else if let Some(monotone) = phash.monotone() {
+            // Values of the vertices of both functions, packed in arrays of words
+
+            let prefix = format!("{namespace}_{name}");
+            let is_64bits = phash.fo_hash().is_64bits();
+
+            for (function_name, function) in [("keys", &monotone.keys), ("buckets", &monotone.buckets)] {
+                let values_name = format!("{prefix}_{function_name}_values");
+                vars.insert("name".to_string(), values_name.clone());
+                vars.insert("type".to_string(), output_lang.map_uint(64).to_string());
+                vars.insert("size".to_string(), format!("{}", function.values.words().len().max(1)));
+
+                write_array(
+                    &mut output_buffer,
+                    &output_lang,
+                    &vars,
+                    function
+                        .values
+                        .words()
+                        .iter()
+                        .chain(function.values.words().is_empty().then_some(&0))
+                        .map(|&word| output_lang.get_word_literal(word)),
+                )?;
+
+                let seeds_name = format!("{prefix}_{function_name}_seeds");
+                vars.insert("name".to_string(), seeds_name.clone());
+                vars.insert("type".to_string(), output_lang.map_uint(if is_64bits { 64 } else { 32 }).to_string());
+                vars.insert("size".to_string(), format!("{}", function.vertex_seeds.len()));
+
+                write_array(
+                    &mut output_buffer,
+                    &output_lang,
+                    &vars,
+                    function.vertex_seeds.iter().map(|&seed| output_lang.get_seed_literal(seed, is_64bits)),
+                )?;
+
+                vars.insert(format!("{function_name}_values_name"), values_name);
+                vars.insert(format!("{function_name}_seeds_name"), seeds_name);
+                vars.insert(format!("{function_name}_part_size"), function.part_size.to_string());
+                vars.insert(format!("{function_name}_width"), function.width.to_string());
+            }
+
+            vars.insert("prefix".to_string(), prefix);
+            vars.insert("seed_type".to_string(), output_lang.map_uint(if is_64bits { 64 } else { 32 }).to_string());
+            vars.insert("prefix_seed".to_string(), output_lang.get_seed_literal(monotone.prefix_seed, is_64bits));
+            vars.insert("rank_bits".to_string(), monotone.rank_bits.to_string());
+            vars.insert("rank_mask".to_string(), ((1u64 << monotone.rank_bits) - 1).to_string());
+        } else if let Some(retrieval) = phash.retrieval() {
+            // Values of the vertices, packed in an array of words
+
+            let prefix = format!("{namespace}_{name}");
+            let values_name = format!("{prefix}_values");
+            vars.insert("name".to_string(), values_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(64).to_string());
+            vars.insert("size".to_string(), format!("{}", retrieval.values.words().len().max(1)));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                retrieval
+                    .values
+                    .words()
+                    .iter()
+                    .chain(retrieval.values.words().is_empty().then_some(&0))
+                    .map(|&word| output_lang.get_word_literal(word)),
+            )?;
+
+            let vertex_seeds_name = format!("{prefix}_vertex_seeds");
+            let is_64bits = phash.fo_hash().is_64bits();
+            vars.insert("name".to_string(), vertex_seeds_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(if is_64bits { 64 } else { 32 }).to_string());
+            vars.insert("size".to_string(), format!("{}", retrieval.vertex_seeds.len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                retrieval.vertex_seeds.iter().map(|&seed| output_lang.get_seed_literal(seed, is_64bits)),
+            )?;
+
+            vars.insert("prefix".to_string(), prefix);
+            vars.insert("vertex_seeds_name".to_string(), vertex_seeds_name);
+            vars.insert("part_size".to_string(), retrieval.part_size.to_string());
+            vars.insert("width".to_string(), retrieval.width.to_string());
+        } else if let Some(recsplit) = phash.recsplit() {
+            // Splitting trees and bucket offsets, packed in a single array of words
+
+            let prefix = format!("{namespace}_{name}");
+            let bits_name = format!("{prefix}_bits");
+            let sections = [
+                &recsplit.tree,
+                &recsplit.positions.lower,
+                &recsplit.positions.upper,
+                &recsplit.offsets.lower,
+                &recsplit.offsets.upper,
+            ];
+
+            let mut section_offsets = Vec::new();
+            let mut words = Vec::new();
+
+            for section in sections {
+                section_offsets.push(words.len());
+                words.extend(section.words().iter().copied());
+            }
+
+            vars.insert("name".to_string(), bits_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(64).to_string());
+            vars.insert("size".to_string(), format!("{}", words.len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                words.iter().map(|&word| output_lang.get_word_literal(word)),
+            )?;
+
+            let samples_name = format!("{prefix}_samples");
+            let samples: Vec<u32> = recsplit
+                .positions
+                .samples
+                .iter()
+                .chain(recsplit.offsets.samples.iter())
+                .copied()
+                .collect();
+            let samples_bits = u32::BITS - samples.iter().max().unwrap_or(&0).leading_zeros();
+            vars.insert("name".to_string(), samples_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(samples_bits).to_string());
+            vars.insert("size".to_string(), format!("{}", samples.len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                samples.iter().map(|sample| sample.to_string()),
+            )?;
+
+            // Unit, Golomb-Rice parameter, fixed bits and nodes of the subtrees, per node size
+
+            let splits_name = format!("{prefix}_splits");
+            let splits: Vec<usize> = recsplit
+                .splits
+                .entries
+                .iter()
+                .flat_map(|split| [split.unit, split.rice, split.skip_bits, split.skip_nodes])
+                .collect();
+            let splits_bits = u64::BITS - (*splits.iter().max().unwrap_or(&0) as u64).leading_zeros();
+            vars.insert("name".to_string(), splits_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(splits_bits).to_string());
+            vars.insert("size".to_string(), format!("{}", splits.len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                splits.iter().map(|value| value.to_string()),
+            )?;
+
+            let start_seeds_name = format!("{prefix}_start_seeds");
+            vars.insert("name".to_string(), start_seeds_name.clone());
+            vars.insert(
+                "type".to_string(),
+                output_lang
+                    .map_seed(&first_bucket.so_hash().seed())
+                    .to_string(),
+            );
+            vars.insert("size".to_string(), format!("{}", recsplit.start_seeds.len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                recsplit
+                    .start_seeds
+                    .iter()
+                    .map(|&seed| output_lang.get_seed_literal(seed, first_bucket.so_hash().is_64bits())),
+            )?;
+
+            let seed_mask = if first_bucket.so_hash().is_64bits() { u64::MAX } else { u32::MAX as u64 };
+
+            vars.insert("prefix".to_string(), prefix);
+            vars.insert("bits_name".to_string(), bits_name);
+            vars.insert("samples_name".to_string(), samples_name);
+            vars.insert("splits_name".to_string(), splits_name);
+            vars.insert("start_seeds_name".to_string(), start_seeds_name);
+            vars.insert("seed_mask".to_string(), format!("{seed_mask:#x}"));
+            vars.insert("num_buckets".to_string(), phash.buckets().len().to_string());
+            vars.insert("positions_lower".to_string(), section_offsets[1].to_string());
+            vars.insert("positions_upper".to_string(), section_offsets[2].to_string());
+            vars.insert("positions_samples".to_string(), "0".to_string());
+            vars.insert("positions_width".to_string(), recsplit.positions.width.to_string());
+            vars.insert("offsets_lower".to_string(), section_offsets[3].to_string());
+            vars.insert("offsets_upper".to_string(), section_offsets[4].to_string());
+            vars.insert("offsets_samples".to_string(), recsplit.positions.samples.len().to_string());
+            vars.insert("offsets_width".to_string(), recsplit.offsets.width.to_string());
+        } else {
+            // Seeds
+
+            let so_seeds_name = format!("{namespace}_{name}_seeds");
+            vars.insert("name".to_string(), so_seeds_name.clone());
+            vars.insert(
+                "type".to_string(),
+                output_lang
+                    .map_seed(&first_bucket.so_hash().seed())
+                    .to_string(),
+            );
+            vars.insert("size".to_string(), format!("{}", phash.buckets().len()));
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                phash
+                    .buckets()
+                    .iter()
+                    .map(|bucket| match bucket.so_hash().seed() {
+                        HashSeed::Bits64(seed) => output_lang.get_word_literal(seed),
+                        seed => seed.to_string(),
+                    }),
+            )?;
+
+            vars.insert("so_seeds_name".to_string(), so_seeds_name);
+            vars.insert("num_seeds".to_string(), phash.buckets().len().to_string());
+            vars.insert("num_groups".to_string(), (phash.table_size() / phash.group_size()).to_string());
+            vars.insert("group_size".to_string(), phash.group_size().to_string());
+
+            if let Some(fks) = phash.fks() {
+                // First slot and number of slots of the table of each bucket
+
+                for (array_name, values) in [("offsets", &fks.offsets), ("sizes", &fks.sizes)] {
+                    let full_name = format!("{namespace}_{name}_{array_name}");
+                    let bits = u32::BITS - values.iter().max().unwrap_or(&0).leading_zeros();
+                    vars.insert("name".to_string(), full_name.clone());
+                    vars.insert("type".to_string(), output_lang.map_uint(bits).to_string());
+                    vars.insert("size".to_string(), format!("{}", values.len()));
+
+                    write_array(
+                        &mut output_buffer,
+                        &output_lang,
+                        &vars,
+                        values.iter().map(|value| value.to_string()),
+                    )?;
+
+                    vars.insert(format!("{array_name}_name"), full_name);
+                }
+            }
+        }
+
+        vars.insert(
+            "key_type".to_string(),
+            output_lang
+                .map_seed(&first_bucket.so_hash().seed())
+                .to_string(),
+        );
+        vars.insert("fo_function_name".to_string(), fo_function_name);
+        vars.insert(
+            "fo_seed".to_string(),
+            output_lang.get_seed_literal(phash.fo_hash().default_seed(), phash.fo_hash().is_64bits()),
+        );
+        vars.insert("so_function_name".to_string(), so_function_name);
+    }
 
     // Values
 
     let values_name = format!("{namespace}_{name}_values");
-    vars.insert("name".to_string(), values_name.clone());
-    vars.insert(
-        "type".to_string(),
-        output_lang.get_type(first_item.item_type()).to_string(),
-    );
-    vars.insert("size".to_string(), format!("{}", phash.m()));
 
-    write!(
-        output_buffer,
-        "{} = {}",
-        strfmt(&output_lang.get_array_decl(), &vars).unwrap(),
-        output_lang.get_array_start()
-    )?;
-    write!(output_buffer, "\n")?;
+    let (compared_keys, sorted_slots) = compared_keys(phash, strategy);
+    let sorted_slots_name = format!("{namespace}_{name}_sorted_slots");
+
+    if matches!(strategy, Strategy::Linear | Strategy::Switch | Strategy::BinarySearch) {
+        vars.insert("name".to_string(), values_name.clone());
+        vars.insert("type".to_string(), value_type.clone());
+        vars.insert("size".to_string(), format!("{}", phash.m()));
+
+        write_array(
+            &mut output_buffer,
+            &output_lang,
+            &vars,
+            compared_keys.iter().map(|&key| value_literal(key)),
+        )?;
+    }
+
+    if let Some(sorted_slots) = sorted_slots.as_ref() {
+        vars.insert("name".to_string(), sorted_slots_name.clone());
+        vars.insert("type".to_string(), output_lang.map_uint(u64::BITS - (phash.m() as u64).leading_zeros()).to_string());
+        vars.insert("size".to_string(), format!("{}", phash.m()));
+
+        write_array(
+            &mut output_buffer,
+            &output_lang,
+            &vars,
+            sorted_slots.iter().map(|slot| slot.to_string()),
+        )?;
+    }
+
+    // Retrieval tables only store the values of the keys, written along with the function
+    if strategy == Strategy::PerfectHash
+        && phash.retrieval().is_none()
+        && let Some(bits) = fingerprint_bits
+    {
+        vars.insert("name".to_string(), values_name.clone());
+        vars.insert("type".to_string(), output_lang.map_uint(bits).to_string());
+        vars.insert("size".to_string(), format!("{}", phash.table_size()));
+
+        // Any fingerprint is as likely to match in empty slots
+        write_array(
+            &mut output_buffer,
+            &output_lang,
+            &vars,
+            phash.slots().iter().map(|slot| match slot {
+                Some(item) => phash.fingerprint(item.item_type(), bits).to_string(),
+                None => "0".to_string(),
+            }),
+        )?;
+    } else if strategy == Strategy::PerfectHash && phash.retrieval().is_none() {
+        vars.insert("name".to_string(), values_name.clone());
+        vars.insert("type".to_string(), value_type.clone());
+        vars.insert("size".to_string(), format!("{}", phash.table_size()));
+
+        // Empty slots hold a key stored in another slot, so that they never match the looked up key
+        let filler = value_literal(&item_type);
+
+        write_array(
+            &mut output_buffer,
+            &output_lang,
+            &vars,
+            phash.slots().iter().map(|slot| match slot {
+                Some(item) => value_literal(item.item_type()),
+                None => filler.clone(),
+            }),
+        )?;
+    }
+
+    // Get function
+
+    let get_function_name = match (phash.gperf().and_then(|gperf| gperf.lookup_function_name.clone()), fingerprint_bits) {
+        (Some(lookup_function_name), _) => lookup_function_name,
+        (None, Some(_)) => format!("{namespace}_{name}_contains"),
+        (None, None) => format!("{namespace}_{name}_get"),
+    };
+
+    // With dense ranges, the lookup of the other keys is only called when the key is in none of them
+    let sparse_function_name = format!("{get_function_name}_sparse");
+
+    // The lookup of normalized keys is called once the key is normalized
+    let normalized_function_name = format!("{get_function_name}_normalized");
+
+    // C byte strings are compared up to the shortest, then by their size
+    let compare_name = format!("{namespace}_{name}_compare");
+
+    // Keywords keeping their case are compared as lowercase: C compares them like strcmp,
+    // Python lowercases them
+    let ignore_case_name = format!("{namespace}_{name}_ignore_case");
+
+    if case_insensitive {
+        vars.insert("name".to_string(), ignore_case_name.clone());
 
-    for item in phash.items().iter() {
         write!(
             output_buffer,
-            "    {}{}",
-            item.item_type().to_string(),
-            output_lang.get_array_sep()
+            "{}",
+            strfmt(
+                &output_lang
+                    .get_get_data("ignore_case")
+                    .expect("Cannot get the ignore case function data")
+                    .body,
+                &vars
+            )
+            .unwrap()
         )?;
-        write!(output_buffer, "\n")?;
+
+        writeln!(output_buffer)?;
     }
 
-    write!(
-        output_buffer,
-        "{}{}",
-        output_lang.get_array_end(),
-        output_lang.get_line_end()
-    )?;
-    write!(output_buffer, "\n")?;
-    write!(output_buffer, "\n")?;
+    if strategy == Strategy::BinarySearch
+        && matches!((&output_lang, &item_type), (OutputLang::C, ItemType::Bytes(_)))
+    {
+        vars.insert("name".to_string(), compare_name.clone());
 
-    // Get function
+        write!(
+            output_buffer,
+            "{}",
+            strfmt(
+                &output_lang
+                    .get_get_data("compare")
+                    .expect("Cannot get the compare function data")
+                    .body,
+                &vars
+            )
+            .unwrap()
+        )?;
+
+        writeln!(output_buffer)?;
+    }
 
-    let get_function_name = format!("{namespace}_{name}_get");
-    vars.insert("name".to_string(), get_function_name);
+    vars.insert(
+        "name".to_string(),
+        if phash.dense().is_some() {
+            sparse_function_name.clone()
+        } else if !normalization.is_empty() {
+            normalized_function_name.clone()
+        } else {
+            get_function_name.clone()
+        },
+    );
     vars.insert(
         "type".to_string(),
-        output_lang.get_type(first_item.item_type()).to_string(),
+        output_lang.get_type(&item_type).to_string(),
     );
+    vars.insert("key_params".to_string(), output_lang.get_key_params(&item_type));
     vars.insert(
-        "key_type".to_string(),
-        output_lang
-            .map_seed(&first_bucket.so_hash().seed())
-            .to_string(),
+        "unused_size".to_string(),
+        match (&output_lang, &item_type) {
+            (OutputLang::C, ItemType::Bytes(_)) => "    (void)len;\n".to_string(),
+            _ => String::new(),
+        },
     );
-    vars.insert("fo_function_name".to_string(), fo_function_name);
-    vars.insert("so_function_name".to_string(), so_function_name);
-    vars.insert("so_seeds_name".to_string(), so_seeds_name);
-    vars.insert("num_seeds".to_string(), phash.buckets().len().to_string());
-    vars.insert("values_name".to_string(), values_name);
+    vars.insert("return_type".to_string(), output_lang.get_return_type(&item_type));
+    vars.insert("value_type".to_string(), value_type.clone());
+    vars.insert("hit".to_string(), "value".to_string());
+    vars.insert("values_name".to_string(), values_name.clone());
     vars.insert("m".to_string(), phash.m().to_string());
+    vars.insert("table_size".to_string(), phash.table_size().to_string());
     vars.insert(
         "key_address".to_string(),
         output_lang
-            .get_key_address(first_item.item_type())
+            .get_key_address(&item_type)
             .to_string(),
     );
     vars.insert(
         "key_size".to_string(),
-        output_lang.get_key_size(first_item.item_type(), "key"),
+        output_lang.get_key_size(&item_type, "key"),
     );
     vars.insert(
         "key_conversion_start".to_string(),
         output_lang
-            .get_key_conversion_start(first_item.item_type())
+            .get_key_conversion_start(&item_type)
             .to_string(),
     );
     vars.insert(
         "key_conversion_end".to_string(),
         output_lang
-            .get_key_conversion_end(first_item.item_type())
+            .get_key_conversion_end(&item_type)
             .to_string(),
     );
+    vars.insert(
+        "key_equals".to_string(),
+        output_lang.get_key_equals(
+            &item_type,
+            &format!("value{key_suffix}"),
+            &format!("value{size_suffix}"),
+            "key",
+            &output_lang.get_key_size(&item_type, "key"),
+        ),
+    );
+    vars.insert("miss".to_string(), miss.clone());
 
-    write!(
-        output_buffer,
-        "{}",
-        strfmt(
-            &output_lang
-                .get_get_data()
-                .expect("Cannot get the get function data")
-                .body,
-            &vars
-        )
-        .unwrap()
-    )?;
+    if let Some(retrieval) = phash.retrieval() {
+        vars.insert("return_type".to_string(), output_lang.map_uint(retrieval.width as u32).to_string());
+    }
+
+    if entry_struct.is_some() {
+        vars.insert("return_type".to_string(), output_lang.get_entry_return_type(&value_type));
+    }
+
+    // Membership tables compare the fingerprint of the key to the stored one
+    if let Some(bits) = fingerprint_bits {
+        let fingerprint_type = output_lang.map_uint(bits);
+        let fo_function_name = vars.get("fo_function_name").cloned().unwrap_or_default();
+        let seed = output_lang.get_seed_literal(phash.fingerprint_seed(), phash.fo_hash().is_64bits());
+
+        let fingerprint = match output_lang {
+            OutputLang::C => format!(
+                "({fingerprint_type}){fo_function_name}({}key, {}, {seed})",
+                output_lang.get_key_address(&item_type),
+                output_lang.get_key_size(&item_type, "key")
+            ),
+            OutputLang::Python => format!(
+                "({fo_function_name}({}key{}, {seed}) & {})",
+                output_lang.get_key_conversion_start(&item_type),
+                output_lang.get_key_conversion_end(&item_type),
+                u64::MAX >> (64 - bits)
+            ),
+        };
+
+        vars.insert("return_type".to_string(), output_lang.get_bool_type().to_string());
+        vars.insert("value_type".to_string(), fingerprint_type.to_string());
+        vars.insert("hit".to_string(), output_lang.get_bool_literal(true).to_string());
+        vars.insert("miss".to_string(), output_lang.get_bool_literal(false).to_string());
+        vars.insert("key_equals".to_string(), format!("value == {fingerprint}"));
+    }
+
+    let ignore_case = case_insensitive.then_some(ignore_case_name.as_str());
+
+    if strategy == Strategy::Switch {
+        vars.insert("cases_name".to_string(), format!("{namespace}_{name}_cases"));
+        vars.insert("case_key".to_string(), output_lang.get_case_key(&item_type, "key"));
+        vars.insert(
+            "cases".to_string(),
+            switch_cases(&output_lang, &compared_keys, &values_name, &key_suffix, ignore_case, &|i| format!("{values_name}[{i}]")),
+        );
+    }
+
+    vars.insert(
+        "key_compare".to_string(),
+        output_lang.get_key_compare(
+            &item_type,
+            "key",
+            &output_lang.get_key_size(&item_type, "key"),
+            &format!("value{key_suffix}"),
+            &format!("value{size_suffix}"),
+            &compare_name,
+        ),
+    );
+
+    if case_insensitive {
+        match output_lang {
+            OutputLang::C => {
+                vars.insert("key_equals".to_string(), format!("{ignore_case_name}(value{key_suffix}, key) == 0"));
+                vars.insert("key_compare".to_string(), format!("{ignore_case_name}(key, value{key_suffix})"));
+            }
+            OutputLang::Python => {
+                vars.insert("key_equals".to_string(), format!("{ignore_case_name}(value{key_suffix}) == key"));
+            }
+        }
+    }
+
+    // Binary searches of unsorted values go through their slots in sorted order
+    let middle = match output_lang {
+        OutputLang::C => "middle",
+        OutputLang::Python => "i",
+    };
+
+    if sorted_slots.is_some() {
+        vars.insert("bsearch_values".to_string(), sorted_slots_name.clone());
+        vars.insert("bsearch_slot".to_string(), format!("{sorted_slots_name}[{middle}]"));
+        vars.insert("bisect_key".to_string(), format!(", key=lambda slot: {values_name}[slot]{key_suffix}"));
+    } else {
+        vars.insert("bsearch_values".to_string(), values_name.clone());
+        vars.insert("bsearch_slot".to_string(), middle.to_string());
+        vars.insert("bisect_key".to_string(), output_lang.get_bisect_key(&key_suffix));
+    }
+
+    // Keywords keeping their case are bisected lowercased
+    if case_insensitive {
+        let (parameter, value) = match sorted_slots {
+            Some(_) => ("slot", format!("{values_name}[slot]{key_suffix}")),
+            None => ("value", format!("value{key_suffix}")),
+        };
+
+        vars.insert("bisect_key".to_string(), format!(", key=lambda {parameter}: {ignore_case_name}({value})"));
+    }
+
+    // Hash tables have a lookup per algorithm
+    let template_name = match strategy {
+        Strategy::PerfectHash => phash.algorithm().name(),
+        _ => strategy.name(),
+    };
+
+    let get_data = output_lang
+        .get_get_data(template_name)
+        .expect("Cannot get the get function data");
+
+    // Without any other key, the ranges miss on their own
+    if phash.dense().is_none() || strategy != Strategy::Empty {
+        if let Some(helpers) = get_data.helpers.as_ref() {
+            write!(output_buffer, "{}", strfmt(helpers, &vars).unwrap())?;
+        }
+
+        write!(output_buffer, "{}", strfmt(&get_data.body, &vars).unwrap())?;
+    }
+
+    // Order-preserving tables also look up the index of the key, -1 when it is missing
+    let index_function_name = format!("{namespace}_{name}_index");
+    let index_normalized_name = format!("{index_function_name}_normalized");
+    let has_index = phash.algorithm().preserves_order();
+
+    if has_index {
+        writeln!(output_buffer)?;
+
+        let mut index_vars = vars.clone();
+
+        index_vars.insert(
+            "name".to_string(),
+            if normalization.is_empty() { index_function_name.clone() } else { index_normalized_name.clone() },
+        );
+        index_vars.insert("return_type".to_string(), output_lang.get_index_type().to_string());
+        index_vars.insert("miss".to_string(), "-1".to_string());
+        index_vars.insert("hit".to_string(), strfmt(get_data.index.as_deref().unwrap_or_default(), &vars).unwrap());
+
+        if strategy == Strategy::Switch {
+            index_vars.insert(
+                "cases".to_string(),
+                switch_cases(&output_lang, &compared_keys, &values_name, &key_suffix, ignore_case, &|i| i.to_string()),
+            );
+        }
+
+        write!(output_buffer, "{}", strfmt(&get_data.body, &index_vars).unwrap())?;
+    }
+
+    if let Some(dense) = phash.dense() {
+        writeln!(output_buffer)?;
+
+        // Start, number of slots and first slot of the table of each range, C compares the
+        // offset of the key from the start as an unsigned 64-bits integer
+
+        let prefix = format!("{namespace}_{name}");
+        let range_starts_name = format!("{prefix}_range_starts");
+        vars.insert("name".to_string(), range_starts_name.clone());
+        vars.insert("type".to_string(), output_lang.map_uint(64).to_string());
+        vars.insert("size".to_string(), format!("{}", dense.ranges.len()));
+
+        write_array(
+            &mut output_buffer,
+            &output_lang,
+            &vars,
+            dense.ranges.iter().map(|range| match output_lang {
+                OutputLang::C => output_lang.get_word_literal(range.start as u64),
+                OutputLang::Python => range.start.to_string(),
+            }),
+        )?;
+
+        let range_sizes_name = format!("{prefix}_range_sizes");
+        let range_offsets_name = format!("{prefix}_range_offsets");
+        let slots_bits = u64::BITS - (dense.slots.len() as u64).leading_zeros();
+
+        for (array_name, values) in [
+            (&range_sizes_name, dense.ranges.iter().map(|range| range.size).collect::<Vec<usize>>()),
+            (&range_offsets_name, dense.ranges.iter().map(|range| range.offset).collect::<Vec<usize>>()),
+        ] {
+            vars.insert("name".to_string(), array_name.clone());
+            vars.insert("type".to_string(), output_lang.map_uint(slots_bits).to_string());
+
+            write_array(
+                &mut output_buffer,
+                &output_lang,
+                &vars,
+                values.iter().map(|value| value.to_string()),
+            )?;
+        }
+
+        let direct_name = format!("{prefix}_direct");
+        vars.insert("name".to_string(), direct_name.clone());
+        vars.insert("type".to_string(), value_type.clone());
+        vars.insert("size".to_string(), format!("{}", dense.slots.len()));
+
+        write_array(
+            &mut output_buffer,
+            &output_lang,
+            &vars,
+            dense.slots.iter().map(value_literal),
+        )?;
+
+        let fallback = match strategy {
+            Strategy::Empty => miss.clone(),
+            _ => format!("{sparse_function_name}(key)"),
+        };
+
+        vars.insert("name".to_string(), get_function_name.clone());
+        vars.insert("type".to_string(), output_lang.get_type(&item_type).to_string());
+        vars.insert("range_starts_name".to_string(), range_starts_name);
+        vars.insert("range_sizes_name".to_string(), range_sizes_name);
+        vars.insert("range_offsets_name".to_string(), range_offsets_name);
+        vars.insert("direct_name".to_string(), direct_name);
+        vars.insert("num_ranges".to_string(), dense.ranges.len().to_string());
+        vars.insert("fallback".to_string(), fallback);
+
+        write!(
+            output_buffer,
+            "{}",
+            strfmt(
+                &output_lang
+                    .get_get_data("ranges")
+                    .expect("Cannot get the get function data")
+                    .body,
+                &vars
+            )
+            .unwrap()
+        )?;
+    }
+
+    if !normalization.is_empty() {
+        writeln!(output_buffer)?;
+
+        let prefix = format!("{namespace}_{name}");
+        let fold_name = format!("{prefix}_fold");
+
+        // Folding the case of the key into the normalized buffer
+        if let Some(case) = normalization.case
+            && matches!(output_lang, OutputLang::C)
+        {
+            let folds = match case {
+                Case::Lower => Vec::new(),
+                Case::Casefold => normalization.folds(phash.items().iter().map(|item| item.item_type())),
+            };
+
+            // Without any non-ASCII character to fold, folding the case is lowercasing it
+            let template_name = if folds.is_empty() { "lower" } else { "casefold" };
+
+            if !folds.is_empty() {
+                let fold_chars_name = format!("{prefix}_fold_chars");
+                let folds_name = format!("{prefix}_folds");
+
+                vars.insert("name".to_string(), fold_chars_name.clone());
+                vars.insert("type".to_string(), "unsigned long".to_string());
+                vars.insert("size".to_string(), folds.len().to_string());
+
+                write_array(
+                    &mut output_buffer,
+                    &output_lang,
+                    &vars,
+                    folds.iter().map(|(c, _)| format!("{c:#x}")),
+                )?;
+
+                vars.insert("name".to_string(), folds_name.clone());
+                vars.insert("type".to_string(), "char*".to_string());
+
+                write_array(
+                    &mut output_buffer,
+                    &output_lang,
+                    &vars,
+                    folds.iter().map(|(_, folded)| output_lang.get_string_literal(folded)),
+                )?;
+
+                vars.insert("fold_chars_name".to_string(), fold_chars_name);
+                vars.insert("folds_name".to_string(), folds_name);
+                vars.insert("num_folds".to_string(), folds.len().to_string());
+            }
+
+            vars.insert("name".to_string(), fold_name.clone());
+
+            write!(
+                output_buffer,
+                "{}",
+                strfmt(
+                    &output_lang
+                        .get_get_data(template_name)
+                        .expect("Cannot get the fold function data")
+                        .body,
+                    &vars
+                )
+                .unwrap()
+            )?;
+
+            writeln!(output_buffer)?;
+        }
+
+        // Keys longer than the longest key once normalized cannot be one, the buffer also holds
+        // the prefix stripped after folding the case
+        let max_size = phash.items().iter().map(|item| item.item_type().hashable().len()).max().unwrap_or(0);
+        let max_prefix_size = normalization.prefixes.iter().map(|prefix| prefix.len()).max().unwrap_or(0);
+
+        vars.insert("type".to_string(), output_lang.get_type(&item_type).to_string());
+        vars.insert("max_size".to_string(), max_size.to_string());
+        vars.insert("buffer_size".to_string(), (max_size + max_prefix_size + 1).to_string());
+        vars.insert("normalized_arguments".to_string(), output_lang.get_key_arguments(&item_type, "normalized", "size"));
+
+        let normalize_data = output_lang
+            .get_get_data("normalize")
+            .expect("Cannot get the normalize function data");
+
+        let mut wrappers = vec![(get_function_name.clone(), normalized_function_name, vars["return_type"].clone(), miss.clone())];
+
+        if has_index {
+            wrappers.push((index_function_name, index_normalized_name, output_lang.get_index_type().to_string(), "-1".to_string()));
+        }
+
+        for (i, (wrapper_name, normalized_name, return_type, wrapper_miss)) in wrappers.into_iter().enumerate() {
+            if i > 0 {
+                writeln!(output_buffer)?;
+            }
+
+            vars.insert("name".to_string(), wrapper_name);
+            vars.insert("normalized_name".to_string(), normalized_name);
+            vars.insert("return_type".to_string(), return_type);
+            vars.insert("miss".to_string(), wrapper_miss.clone());
+            vars.insert(
+                "steps".to_string(),
+                output_lang.get_normalization_steps(normalization, &item_type, &fold_name, &wrapper_miss),
+            );
+
+            write!(output_buffer, "{}", strfmt(&normalize_data.body, &vars).unwrap())?;
+        }
+    }
+
+    if let Some(gperf) = phash.gperf()
+        && matches!(output_lang, OutputLang::C)
+        && !gperf.functions.is_empty()
+    {
+        writeln!(output_buffer)?;
+        write!(output_buffer, "{}", gperf.functions)?;
+    }
 
     println!("Wrote to \"{}\" successfully", output_path.display());
 
     return Ok(());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phash::{Algorithm, BuildParams};
+
+    const KEYS: [&str; 6] = ["delta", "alpha", "foxtrot", "echo", "charlie", "bravo"];
+
+    fn build(algorithm: Algorithm) -> PHash {
+        let keys: Vec<ItemType> = KEYS.iter().map(|key| ItemType::Str(key.to_string())).collect();
+
+        let params = BuildParams {
+            algorithm,
+            bucket_size: algorithm.default_bucket_size(),
+            load_factor: algorithm.default_load_factor(),
+            jobs: 1,
+            seed: Some(1),
+            verbose: false,
+            ..BuildParams::default()
+        };
+
+        return PHash::from_keys(&keys, &params).unwrap();
+    }
+
+    fn strings(keys: &[&ItemType]) -> Vec<String> {
+        return keys
+            .iter()
+            .map(|key| match key {
+                ItemType::Str(s) => s.clone(),
+                _ => panic!("Expected string keys"),
+            })
+            .collect();
+    }
+
+    #[test]
+    fn explicit_algorithm_is_looked_up_by_perfect_hash() {
+        assert_eq!(Strategy::requested(None, true), Ok(Some(Strategy::PerfectHash)));
+        assert_eq!(Strategy::requested(None, false), Ok(None));
+        assert_eq!(Strategy::requested(Some("auto"), true), Ok(None));
+        assert_eq!(Strategy::requested(Some("switch"), true), Ok(Some(Strategy::Switch)));
+        assert!(Strategy::requested(Some("hash"), false).is_err());
+    }
+
+    #[test]
+    fn order_preserving_tables_keep_their_slot_order() {
+        let chm = build(Algorithm::Chm);
+
+        for strategy in [Strategy::Linear, Strategy::Switch, Strategy::BinarySearch] {
+            let (keys, sorted_slots) = compared_keys(&chm, strategy);
+
+            assert_eq!(strings(&keys), KEYS, "{} lookup", strategy.name());
+
+            if strategy == Strategy::BinarySearch {
+                let sorted: Vec<&ItemType> = sorted_slots.unwrap().iter().map(|&slot| keys[slot]).collect();
+                let mut expected = KEYS.to_vec();
+                expected.sort();
+
+                assert_eq!(strings(&sorted), expected);
+            } else {
+                assert!(sorted_slots.is_none());
+            }
+        }
+
+        // Ranks are the sorted order, no indirection is needed
+        let monotone = build(Algorithm::Monotone);
+        let mut sorted = KEYS.to_vec();
+        sorted.sort();
+
+        for strategy in [Strategy::Linear, Strategy::Switch, Strategy::BinarySearch] {
+            let (keys, sorted_slots) = compared_keys(&monotone, strategy);
+
+            assert_eq!(strings(&keys), sorted, "{} lookup", strategy.name());
+            assert!(sorted_slots.is_none());
+        }
+    }
+
+    #[test]
+    fn other_tables_are_compared_in_sorted_order() {
+        let chd = build(Algorithm::Chd);
+        let mut sorted = KEYS.to_vec();
+        sorted.sort();
+
+        for strategy in [Strategy::Switch, Strategy::BinarySearch] {
+            let (keys, sorted_slots) = compared_keys(&chd, strategy);
+
+            assert_eq!(strings(&keys), sorted, "{} lookup", strategy.name());
+            assert!(sorted_slots.is_none());
+        }
+    }
+}
@@ -0,0 +1,389 @@
+// https://www.gnu.org/software/gperf/manual/gperf.html#Input-Format
+
+use super::input::InputFile;
+use std::path::Path;
+
+/// Keyword line of a gperf file
+#[derive(Debug, Clone)]
+pub struct Keyword {
+    pub name: String,
+    /// Other fields of the struct initializer of the keyword, verbatim
+    pub fields: Vec<String>,
+    /// Line of the keyword in the file
+    pub line: usize,
+}
+
+/// Input file of gperf: declarations, keywords and functions sections, separated by %% lines
+#[derive(Debug, Clone, Default)]
+pub struct Gperf {
+    /// Code of the %{ %} blocks of the declarations, copied at the start of the generated C file
+    pub code: String,
+    /// Declaration of the struct of the keywords, with %struct-type
+    pub struct_declaration: Option<String>,
+    /// Name of the struct of the keywords, with %struct-type
+    pub struct_name: Option<String>,
+    /// Name of the keyword field of the struct, %define slot-name
+    pub slot_name: String,
+    /// Name of the generated lookup function, %define lookup-function-name
+    pub lookup_function_name: Option<String>,
+    /// The struct is declared by the %{ %} code, %omit-struct-type
+    pub omit_struct_type: bool,
+    /// Keywords are compared case-insensitively (ASCII), %ignore-case
+    pub ignore_case: bool,
+    pub keywords: Vec<Keyword>,
+    /// Functions section, copied at the end of the generated C file
+    pub functions: String,
+    /// Options tuning the code generated by gperf, pho generates its own lookup
+    pub ignored_options: Vec<String>,
+}
+
+impl Gperf {
+    pub fn is_gperf(path: &Path) -> bool {
+        return path.extension().is_some_and(|extension| extension == "gperf");
+    }
+
+    pub fn read(file: &InputFile) -> Result<Gperf, Box<dyn std::error::Error>> {
+        let content = file.text().map_err(|e| format!("{}:{}", file.name(), e))?;
+
+        return Gperf::parse(content).map_err(|e| format!("{}:{}", file.name(), e).into());
+    }
+
+    /// Parses the content of a gperf file, errors start with the line they occur on
+    pub fn parse(content: &str) -> Result<Gperf, String> {
+        let mut gperf = Gperf {
+            slot_name: "name".to_string(),
+            ..Default::default()
+        };
+
+        let lines: Vec<(usize, &str)> = content
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.strip_suffix('\r').unwrap_or(line)))
+            .collect();
+
+        // Without any %% line, the whole file is keywords
+        let separators: Vec<usize> = lines.iter().filter(|(_, line)| *line == "%%").map(|(n, _)| *n).collect();
+
+        let (declarations, keywords, functions) = match separators.as_slice() {
+            [] => (&lines[..0], &lines[..], &lines[..0]),
+            [first] => (&lines[..first - 1], &lines[*first..], &lines[..0]),
+            [first, second, ..] => (&lines[..first - 1], &lines[*first..second - 1], &lines[*second..]),
+        };
+
+        let mut delimiters = ",".to_string();
+        let mut struct_type = None;
+        let mut struct_declaration = String::new();
+        let mut code_start = None;
+
+        for &(n, line) in declarations.iter() {
+            if code_start.is_some() {
+                if line.trim_end() == "%}" {
+                    code_start = None;
+                } else {
+                    gperf.code += line;
+                    gperf.code += "\n";
+                }
+
+                continue;
+            }
+
+            if line.trim_end() == "%{" {
+                code_start = Some(n);
+                continue;
+            }
+
+            let Some(option) = line.strip_prefix('%') else {
+                if !line.trim().is_empty() {
+                    struct_declaration += line;
+                    struct_declaration += "\n";
+                }
+
+                continue;
+            };
+
+            let option = option.trim();
+            let (option_name, value) = match option.split_once(['=', ' ', '\t']) {
+                Some((option_name, value)) => (option_name, value.trim()),
+                None => (option, ""),
+            };
+
+            match option_name {
+                "struct-type" => struct_type = Some(n),
+                "omit-struct-type" => gperf.omit_struct_type = true,
+                "delimiters" => delimiters = unquote(value),
+                "ignore-case" => gperf.ignore_case = true,
+                "define" => match value.split_once([' ', '\t']) {
+                    Some(("slot-name", slot_name)) => gperf.slot_name = slot_name.trim().to_string(),
+                    Some(("lookup-function-name", name)) => gperf.lookup_function_name = Some(name.trim().to_string()),
+                    // Code calling the hash function of gperf would get another function
+                    Some(("hash-function-name", _)) => {
+                        return Err(format!("{n}: %define hash-function-name is not supported, pho generates its own hash functions"));
+                    }
+                    Some(_) => gperf.ignored_options.push(format!("%{option}")),
+                    None => return Err(format!("{n}: expected a name and a value after %define")),
+                },
+                _ => gperf.ignored_options.push(format!("%{option}")),
+            }
+        }
+
+        if let Some(start) = code_start {
+            return Err(format!("{start}: %{{ block without a closing %}}"));
+        }
+
+        if let Some(n) = struct_type {
+            let name = struct_declaration
+                .split_once("struct")
+                .map(|(_, rest)| rest.trim_start())
+                .map(|rest| rest.split(|c: char| !(c.is_alphanumeric() || c == '_')).next().unwrap_or_default())
+                .filter(|name| !name.is_empty())
+                .ok_or_else(|| format!("{n}: %struct-type without a struct declaration"))?;
+
+            gperf.struct_name = Some(name.to_string());
+            gperf.struct_declaration = Some(struct_declaration);
+        }
+
+        for &(n, line) in keywords.iter() {
+            // Comments start with a # in the first column
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, rest) = match line.strip_prefix('"') {
+                Some(quoted) => {
+                    let (name, length) = unescape(quoted).map_err(|e| format!("{n}: {e}"))?;
+                    (name, &quoted[length..])
+                }
+                None => {
+                    let end = line.find(|c| delimiters.contains(c)).unwrap_or(line.len());
+                    (line[..end].to_string(), &line[end..])
+                }
+            };
+
+            let rest = rest.trim_start();
+
+            let fields = match rest.chars().next() {
+                None => Vec::new(),
+                Some(c) if delimiters.contains(c) => split_fields(&rest[c.len_utf8()..]).map_err(|e| format!("{n}: {e}"))?,
+                Some(_) => return Err(format!("{n}: expected a delimiter after keyword \"{name}\"")),
+            };
+
+            gperf.keywords.push(Keyword { name, fields, line: n });
+        }
+
+        for &(_, line) in functions.iter() {
+            gperf.functions += line;
+            gperf.functions += "\n";
+        }
+
+        return Ok(gperf);
+    }
+
+    /// Initializer of the struct of a keyword, with the given literal of the keyword
+    pub fn initializer(keyword: &Keyword, name_literal: &str) -> String {
+        return std::iter::once(name_literal.to_string())
+            .chain(keyword.fields.iter().cloned())
+            .collect::<Vec<String>>()
+            .join(", ");
+    }
+}
+
+// Value of an option, without the quotes around it
+fn unquote(value: &str) -> String {
+    return value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_string();
+}
+
+// Decodes a C string literal, after its opening quote. Returns the string and the length of the
+// literal, closing quote included
+fn unescape(quoted: &str) -> Result<(String, usize), String> {
+    let bytes = quoted.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                let name = String::from_utf8(decoded).map_err(|_| "keyword is not valid UTF-8".to_string())?;
+                return Ok((name, i + 1));
+            }
+            b'\\' if i + 1 < bytes.len() => {
+                i += 1;
+
+                match bytes[i] {
+                    b'n' => decoded.push(b'\n'),
+                    b't' => decoded.push(b'\t'),
+                    b'r' => decoded.push(b'\r'),
+                    b'a' => decoded.push(0x07),
+                    b'b' => decoded.push(0x08),
+                    b'f' => decoded.push(0x0c),
+                    b'v' => decoded.push(0x0b),
+                    b'x' => {
+                        let digits = bytes[i + 1..].iter().take_while(|b| b.is_ascii_hexdigit()).count();
+
+                        if digits == 0 {
+                            return Err("\\x without hexadecimal digits".to_string());
+                        }
+
+                        let value = u32::from_str_radix(&quoted[i + 1..i + 1 + digits], 16).unwrap_or(u32::MAX);
+                        decoded.push(u8::try_from(value).map_err(|_| "\\x escape out of range".to_string())?);
+                        i += digits;
+                    }
+                    b'0'..=b'7' => {
+                        let digits = bytes[i..].iter().take(3).take_while(|b| (b'0'..=b'7').contains(b)).count();
+                        let value = u32::from_str_radix(&quoted[i..i + digits], 8).unwrap_or(u32::MAX);
+                        decoded.push(u8::try_from(value).map_err(|_| "octal escape out of range".to_string())?);
+                        i += digits - 1;
+                    }
+                    other => decoded.push(other),
+                }
+            }
+            other => decoded.push(other),
+        }
+
+        i += 1;
+    }
+
+    return Err("unterminated string".to_string());
+}
+
+// Splits the fields of a keyword line on the commas outside of literals, parentheses and braces
+fn split_fields(rest: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut depth = 0_i32;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in rest.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+
+            continue;
+        }
+
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                fields.push(rest[start..i].trim().to_string());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    if quote.is_some() {
+        return Err("unterminated literal in the fields".to_string());
+    }
+
+    fields.push(rest[start..].trim().to_string());
+    fields.retain(|field| !field.is_empty());
+
+    return Ok(fields);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYWORDS: &str = r#"%{
+#include "tokens.h"
+%}
+%struct-type
+%ignore-case
+%define slot-name keyword
+%define lookup-function-name in_word_set
+%compare-strncmp
+struct token { const char* keyword; int id; const char* name; };
+%%
+# comment
+if, TOKEN_IF, "if"
+else, TOKEN_ELSE, f(1, 2)
+%%
+int used(void) { return 0; }
+"#;
+
+    #[test]
+    fn declarations_set_the_struct_and_options() {
+        let gperf = Gperf::parse(KEYWORDS).unwrap();
+
+        assert_eq!(gperf.code, "#include \"tokens.h\"\n");
+        assert_eq!(gperf.struct_name.as_deref(), Some("token"));
+        assert_eq!(
+            gperf.struct_declaration.as_deref(),
+            Some("struct token { const char* keyword; int id; const char* name; };\n")
+        );
+        assert_eq!(gperf.slot_name, "keyword");
+        assert_eq!(gperf.lookup_function_name.as_deref(), Some("in_word_set"));
+        assert!(gperf.ignore_case);
+        assert!(!gperf.omit_struct_type);
+        assert_eq!(gperf.ignored_options, ["%compare-strncmp"]);
+        assert_eq!(gperf.functions, "int used(void) { return 0; }\n");
+    }
+
+    #[test]
+    fn struct_type_keywords_keep_their_other_fields() {
+        let gperf = Gperf::parse(KEYWORDS).unwrap();
+
+        let keywords: Vec<(&str, Vec<&str>, usize)> = gperf
+            .keywords
+            .iter()
+            .map(|keyword| (keyword.name.as_str(), keyword.fields.iter().map(String::as_str).collect(), keyword.line))
+            .collect();
+
+        assert_eq!(
+            keywords,
+            [("if", vec!["TOKEN_IF", "\"if\""], 12), ("else", vec!["TOKEN_ELSE", "f(1, 2)"], 13)]
+        );
+        assert_eq!(Gperf::initializer(&gperf.keywords[1], "\"else\""), "\"else\", TOKEN_ELSE, f(1, 2)");
+    }
+
+    #[test]
+    fn delimiters_and_quoted_keywords() {
+        let gperf = Gperf::parse("%delimiters=\";\"\n%%\nplain;1\n\"semi;colon\";2\n\"tab\\there\\x41\\101\"\n").unwrap();
+
+        let keywords: Vec<(&str, Vec<&str>)> = gperf
+            .keywords
+            .iter()
+            .map(|keyword| (keyword.name.as_str(), keyword.fields.iter().map(String::as_str).collect()))
+            .collect();
+
+        assert_eq!(keywords, [("plain", vec!["1"]), ("semi;colon", vec!["2"]), ("tab\thereAA", vec![])]);
+    }
+
+    #[test]
+    fn files_without_separators_are_keywords() {
+        let gperf = Gperf::parse("alpha\nbeta\n").unwrap();
+
+        assert_eq!(gperf.keywords.len(), 2);
+        assert!(gperf.struct_name.is_none());
+        assert_eq!(gperf.slot_name, "name");
+    }
+
+    #[test]
+    fn malformed_files_are_errors_with_their_line() {
+        let cases = [
+            ("%{\nint x;\n%%\nif\n", "1: %{ block without a closing %}"),
+            ("%struct-type\n%%\nif\n", "1: %struct-type without a struct declaration"),
+            ("%define\n%%\nif\n", "1: expected a name and a value after %define"),
+            ("%define hash-function-name h\n%%\nif\n", "1: %define hash-function-name is not supported, pho generates its own hash functions"),
+            ("%%\n\"unterminated\n", "2: unterminated string"),
+            ("%%\n\"if\" 1\n", "2: expected a delimiter after keyword \"if\""),
+            ("%%\nif, \"open\n", "2: unterminated literal in the fields"),
+        ];
+
+        for (content, expected) in cases {
+            assert_eq!(Gperf::parse(content).unwrap_err(), expected);
+        }
+    }
+}
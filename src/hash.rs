@@ -12,7 +12,7 @@ pub enum HashKey {
 
 impl Default for HashKey {
     fn default() -> Self {
-        return HashKey::from(0 as u32);
+        return HashKey::from(0_u32);
     }
 }
 
@@ -37,18 +37,18 @@ impl From<u64> for HashKey {
     }
 }
 
-impl Into<u32> for HashKey {
-    fn into(self) -> u32 {
-        match self {
+impl From<HashKey> for u32 {
+    fn from(val: HashKey) -> Self {
+        match val {
             HashKey::Bits32(x) => x,
             HashKey::Bits64(x) => x as u32,
         }
     }
 }
 
-impl Into<u64> for HashKey {
-    fn into(self) -> u64 {
-        match self {
+impl From<HashKey> for u64 {
+    fn from(val: HashKey) -> Self {
+        match val {
             HashKey::Bits32(x) => x as u64,
             HashKey::Bits64(x) => x,
         }
@@ -61,7 +61,7 @@ impl Rem<u32> for HashKey {
     fn rem(self, rhs: u32) -> Self::Output {
         match self {
             HashKey::Bits32(x) => x % rhs,
-            HashKey::Bits64(x) => x as u32 % rhs,
+            HashKey::Bits64(x) => (x % rhs as u64) as u32,
         }
     }
 }
@@ -87,6 +87,14 @@ impl HashKey {
     }
 }
 
+/// Mixes a 64-bits integer, used to derive independent seeds from a single one
+pub fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    return z ^ (z >> 31);
+}
+
 // First-order hash functions
 
 #[derive(Debug, Clone)]
@@ -103,12 +111,15 @@ impl Default for FNV1A {
 }
 
 impl FNV1A {
-    fn hash(h: &Hashable) -> HashKey {
-        let mut result = 0x811c9dc5 as u32;
+    // Offset basis
+    const DEFAULT_SEED: u32 = 0x811c9dc5;
+
+    fn hash(h: &[u8], seed: u32) -> HashKey {
+        let mut result = seed;
 
         for d in h {
             result ^= *d as u32;
-            result = result.wrapping_mul(0x01000193 as u32);
+            result = result.wrapping_mul(0x01000193_u32);
         }
 
         return HashKey::from(result);
@@ -129,14 +140,16 @@ impl Default for XXHash32 {
 }
 
 impl XXHash32 {
-    fn hash(h: &Hashable) -> HashKey {
+    const DEFAULT_SEED: u32 = 0;
+
+    fn hash(h: &[u8], seed: u32) -> HashKey {
         const PRIME1: u32 = 0x9E3779B1;
         const PRIME2: u32 = 0x85EBCA6B;
         const PRIME3: u32 = 0xC2B2AE35;
         const PRIME4: u32 = 0x27D4EB2F;
         const PRIME5: u32 = 0x165667B1;
 
-        let mut res = PRIME5.wrapping_add(h.len() as u32);
+        let mut res = seed.wrapping_add(PRIME5).wrapping_add(h.len() as u32);
 
         let chunks = h.chunks_exact(4);
         let remainder = chunks.remainder();
@@ -180,8 +193,9 @@ impl Default for Murmur3 {
 }
 
 impl Murmur3 {
-    fn hash(h: &Hashable) -> HashKey {
-        const SEED: u32 = 0x8286ff1d;
+    const DEFAULT_SEED: u32 = 0x8286ff1d;
+
+    fn hash(h: &[u8], seed: u32) -> HashKey {
         const C1: u32 = 0xcc9e2d51;
         const C2: u32 = 0x1b873593;
         const C3: u32 = 0xe6546b64;
@@ -190,7 +204,7 @@ impl Murmur3 {
 
         let data = h;
         let len = data.len();
-        let mut hash = SEED;
+        let mut hash = seed;
         let mut i = 0;
 
         // Process 4-byte chunks
@@ -248,7 +262,9 @@ impl Default for XXHash64 {
 }
 
 impl XXHash64 {
-    fn hash(h: &Hashable) -> HashKey {
+    const DEFAULT_SEED: u64 = 0;
+
+    fn hash(h: &[u8], seed: u64) -> HashKey {
         const PRIME1: u64 = 0x9e3779b185ebca87;
         const PRIME2: u64 = 0xc2b2ae3d27d4eb4f;
         const PRIME3: u64 = 0x165667b19e3779f9;
@@ -261,10 +277,10 @@ impl XXHash64 {
         let mut i = 0;
 
         if len >= 32 {
-            let mut v1 = PRIME1.wrapping_add(PRIME2);
-            let mut v2 = PRIME2;
-            let mut v3 = 0u64;
-            let mut v4 = PRIME1.wrapping_neg();
+            let mut v1 = seed.wrapping_add(PRIME1).wrapping_add(PRIME2);
+            let mut v2 = seed.wrapping_add(PRIME2);
+            let mut v3 = seed;
+            let mut v4 = seed.wrapping_sub(PRIME1);
 
             while i + 32 <= len {
                 v1 = v1.wrapping_add(
@@ -319,7 +335,7 @@ impl XXHash64 {
             hash ^= v4;
             hash = hash.wrapping_mul(PRIME1).wrapping_add(PRIME4);
         } else {
-            hash = PRIME5;
+            hash = seed.wrapping_add(PRIME5);
         }
 
         hash = hash.wrapping_add(len as u64);
@@ -363,12 +379,194 @@ impl XXHash64 {
     }
 }
 
+/// gperf-style hash of the length of the key and of its bytes at a few positions, selected on
+/// the keyset so that no two keys have the same length and bytes at these positions
+#[derive(Debug, Clone)]
+pub struct Positions {
+    name: String,
+    /// From the start of the key, or from its end when negative, -1 being the last byte
+    positions: Vec<i32>,
+}
+
+impl Default for Positions {
+    fn default() -> Self {
+        return Self {
+            name: "positions".to_string(),
+            positions: Vec::new(),
+        };
+    }
+}
+
+impl Positions {
+    const DEFAULT_SEED: u32 = 0x811c9dc5;
+
+    const PRIME: u32 = 0x01000193;
+
+    /// Positions further than that from both ends of the keys are not considered
+    pub const MAX_POSITION: usize = 256;
+
+    /// Byte of the key at a position, None past its end
+    fn byte(h: &[u8], position: i32) -> Option<u8> {
+        let index = match usize::try_from(position) {
+            Ok(index) => index,
+            Err(_) => h.len().checked_sub(position.unsigned_abs() as usize)?,
+        };
+
+        return h.get(index).copied();
+    }
+
+    fn hash(&self, h: &[u8], seed: u32) -> HashKey {
+        let mut hash = (seed ^ h.len() as u32).wrapping_mul(Positions::PRIME);
+
+        for &position in self.positions.iter() {
+            hash ^= Positions::byte(h, position).unwrap_or(0) as u32;
+            hash = hash.wrapping_mul(Positions::PRIME);
+        }
+
+        // Murmur3 finalization, the bytes are only mixed once
+        hash ^= hash >> 16;
+        hash = hash.wrapping_mul(0x85ebca6b);
+        hash ^= hash >> 13;
+        hash = hash.wrapping_mul(0xc2b2ae35);
+        hash ^= hash >> 16;
+
+        return HashKey::from(hash);
+    }
+
+    pub fn positions(&self) -> &[i32] {
+        return &self.positions;
+    }
+
+    /// Positions in gperf notation: 1-based from the start, $ for the last byte
+    pub fn describe(&self) -> String {
+        if self.positions.is_empty() {
+            return "none, the length tells the keys apart".to_string();
+        }
+
+        return self
+            .positions
+            .iter()
+            .map(|&position| match position {
+                -1 => "$".to_string(),
+                _ if position < 0 => format!("$-{}", -position - 1),
+                _ => format!("{}", position + 1),
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+    }
+
+    /// Greedily adds the position telling the most keys apart, until their length and bytes at
+    /// the selected positions are unique, then drops the positions made redundant by the others
+    pub fn select(keys: &[Hashable]) -> Result<Vec<i32>, String> {
+        let mut keys: Vec<&[u8]> = keys.iter().map(|key| key.as_slice()).collect();
+        keys.sort_unstable();
+        keys.dedup();
+
+        // Past the end of a key reads as an extra byte value
+        let byte_class = |key: &[u8], position: i32| Positions::byte(key, position).map_or(256, usize::from);
+
+        // Closest to both ends first, so that ties pick the bytes most keys have
+        let max_len = keys.iter().map(|key| key.len()).max().unwrap_or(0).min(Positions::MAX_POSITION);
+        let candidates: Vec<i32> = (0..max_len as i32).flat_map(|i| [i, -i - 1]).collect();
+
+        // Keys not told apart yet, grouped by length and bytes at the selected positions
+        let mut groups: Vec<Vec<&[u8]>> = Vec::new();
+        keys.sort_by_key(|key| key.len());
+
+        for group in keys.chunk_by(|a, b| a.len() == b.len()) {
+            if group.len() > 1 {
+                groups.push(group.to_vec());
+            }
+        }
+
+        let mut positions = Vec::new();
+        let mut seen = [usize::MAX; 257];
+
+        while !groups.is_empty() {
+            let mut best: Option<(usize, i32)> = None;
+
+            for &candidate in candidates.iter().filter(|candidate| !positions.contains(*candidate)) {
+                seen.fill(usize::MAX);
+
+                let mut gain = 0;
+
+                for (g, group) in groups.iter().enumerate() {
+                    for key in group.iter() {
+                        let class = byte_class(key, candidate);
+
+                        if seen[class] != g {
+                            seen[class] = g;
+                            gain += 1;
+                        }
+                    }
+
+                    gain -= 1;
+                }
+
+                if best.is_none_or(|(best_gain, _)| gain > best_gain) {
+                    best = Some((gain, candidate));
+                }
+            }
+
+            let position = match best {
+                Some((gain, position)) if gain > 0 => position,
+                _ => {
+                    return Err(format!(
+                        "Cannot find key positions telling the keys apart within {} bytes of their ends, use another first-order hash",
+                        Positions::MAX_POSITION
+                    ));
+                }
+            };
+
+            positions.push(position);
+
+            groups = groups
+                .into_iter()
+                .flat_map(|mut group| {
+                    group.sort_by_key(|key| byte_class(key, position));
+
+                    return group
+                        .chunk_by(|a, b| byte_class(a, position) == byte_class(b, position))
+                        .filter(|group| group.len() > 1)
+                        .map(|group| group.to_vec())
+                        .collect::<Vec<Vec<&[u8]>>>();
+                })
+                .collect();
+        }
+
+        let unique = |positions: &[i32]| {
+            let mut signatures: Vec<(usize, Vec<usize>)> = keys
+                .iter()
+                .map(|key| (key.len(), positions.iter().map(|&position| byte_class(key, position)).collect()))
+                .collect();
+
+            signatures.sort_unstable();
+
+            return signatures.windows(2).all(|pair| pair[0] != pair[1]);
+        };
+
+        for i in (0..positions.len()).rev() {
+            let mut without = positions.clone();
+            without.remove(i);
+
+            if unique(&without) {
+                positions = without;
+            }
+        }
+
+        positions.sort_by_key(|&position| (position < 0, position.abs()));
+
+        return Ok(positions);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum FOHash {
     FNV1A(FNV1A),
     XXHash32(XXHash32),
     Murmur3(Murmur3),
     XXHash64(XXHash64),
+    Positions(Positions),
 }
 
 impl Default for FOHash {
@@ -386,18 +584,58 @@ impl FromStr for FOHash {
             "xxhash32" => Ok(FOHash::XXHash32(XXHash32::default())),
             "murmur3" => Ok(FOHash::Murmur3(Murmur3::default())),
             "xxhash64" => Ok(FOHash::XXHash64(XXHash64::default())),
-            _ => Err("Cannot find a corresponding first-order hash. Expected: fnv1a, xxhash32, murmur3, xxhash64".into()),
+            "positions" => Ok(FOHash::Positions(Positions::default())),
+            _ => Err("Cannot find a corresponding first-order hash. Expected: fnv1a, xxhash32, murmur3, xxhash64, positions"),
         }
     }
 }
 
 impl FOHash {
+    /// Every registered first-order hash, in registration order
+    pub fn all() -> Vec<FOHash> {
+        return vec![
+            FOHash::FNV1A(FNV1A::default()),
+            FOHash::XXHash32(XXHash32::default()),
+            FOHash::Murmur3(Murmur3::default()),
+            FOHash::XXHash64(XXHash64::default()),
+            FOHash::Positions(Positions::default()),
+        ];
+    }
+
     pub fn hash(&self, h: &Hashable) -> HashKey {
+        return self.hash_with_seed(h, self.default_seed());
+    }
+
+    /// Hashes with another seed, to derive independent hash functions from the same one.
+    /// 32-bits hashes only use the low 32 bits of the seed
+    pub fn hash_with_seed(&self, h: &[u8], seed: u64) -> HashKey {
         match self {
-            FOHash::FNV1A(_) => FNV1A::hash(h),
-            FOHash::XXHash32(_) => XXHash32::hash(h),
-            FOHash::Murmur3(_) => Murmur3::hash(h),
-            FOHash::XXHash64(_) => XXHash64::hash(h),
+            FOHash::FNV1A(_) => FNV1A::hash(h, seed as u32),
+            FOHash::XXHash32(_) => XXHash32::hash(h, seed as u32),
+            FOHash::Murmur3(_) => Murmur3::hash(h, seed as u32),
+            FOHash::XXHash64(_) => XXHash64::hash(h, seed),
+            FOHash::Positions(p) => p.hash(h, seed as u32),
+        }
+    }
+
+    /// Fits the hash to the keyset, only the positions hash depends on the keys, through the
+    /// positions it reads
+    pub fn fit(&mut self, keys: &[Hashable]) -> Result<(), String> {
+        if let FOHash::Positions(p) = self {
+            p.positions = Positions::select(keys)?;
+        }
+
+        return Ok(());
+    }
+
+    /// Seed used to hash the keys
+    pub fn default_seed(&self) -> u64 {
+        match self {
+            FOHash::FNV1A(_) => FNV1A::DEFAULT_SEED as u64,
+            FOHash::XXHash32(_) => XXHash32::DEFAULT_SEED as u64,
+            FOHash::Murmur3(_) => Murmur3::DEFAULT_SEED as u64,
+            FOHash::XXHash64(_) => XXHash64::DEFAULT_SEED,
+            FOHash::Positions(_) => Positions::DEFAULT_SEED as u64,
         }
     }
 
@@ -407,6 +645,7 @@ impl FOHash {
             FOHash::XXHash32(h) => h.name.as_str(),
             FOHash::Murmur3(h) => h.name.as_str(),
             FOHash::XXHash64(h) => h.name.as_str(),
+            FOHash::Positions(h) => h.name.as_str(),
         }
     }
 
@@ -416,6 +655,19 @@ impl FOHash {
             FOHash::XXHash32(_) => false,
             FOHash::Murmur3(_) => false,
             FOHash::XXHash64(_) => true,
+            FOHash::Positions(_) => false,
+        }
+    }
+
+    /// Rough number of arithmetic operations needed to hash a key of `len` bytes,
+    /// used to compare the lookup cost of the different hashes
+    pub fn cost(&self, len: f64) -> f64 {
+        match self {
+            FOHash::FNV1A(_) => 2.0 + len * 2.0,
+            FOHash::XXHash32(_) => 12.0 + (len / 4.0).ceil() * 4.0,
+            FOHash::Murmur3(_) => 14.0 + (len / 4.0).ceil() * 7.0,
+            FOHash::XXHash64(_) => 16.0 + (len / 8.0).ceil() * 4.0 + (len % 8.0) * 2.0,
+            FOHash::Positions(h) => 10.0 + h.positions.len() as f64 * 4.0,
         }
     }
 }
@@ -457,9 +709,9 @@ impl From<u128> for HashSeed {
     }
 }
 
-impl Into<u32> for HashSeed {
-    fn into(self) -> u32 {
-        match self {
+impl From<HashSeed> for u32 {
+    fn from(val: HashSeed) -> Self {
+        match val {
             HashSeed::Bits32(x) => x,
             HashSeed::Bits64(x) => x as u32,
             HashSeed::Bits128(x) => x as u32,
@@ -467,9 +719,9 @@ impl Into<u32> for HashSeed {
     }
 }
 
-impl Into<u64> for HashSeed {
-    fn into(self) -> u64 {
-        match self {
+impl From<HashSeed> for u64 {
+    fn from(val: HashSeed) -> Self {
+        match val {
             HashSeed::Bits32(x) => x as u64,
             HashSeed::Bits64(x) => x,
             HashSeed::Bits128(x) => x as u64,
@@ -477,9 +729,9 @@ impl Into<u64> for HashSeed {
     }
 }
 
-impl Into<u128> for HashSeed {
-    fn into(self) -> u128 {
-        match self {
+impl From<HashSeed> for u128 {
+    fn from(val: HashSeed) -> Self {
+        match val {
             HashSeed::Bits32(x) => x as u128,
             HashSeed::Bits64(x) => x as u128,
             HashSeed::Bits128(x) => x,
@@ -520,7 +772,7 @@ impl MXF {
     fn hash(&self, key: HashKey) -> HashKey {
         let mut h: u64 = (key.as_u64()).wrapping_mul(self.seed.as_u64() | 1);
         h ^= h >> 33;
-        h = h.wrapping_mul(0xff51afd7ed558ccd as u64);
+        h = h.wrapping_mul(0xff51afd7ed558ccd_u64);
         h ^= h >> 33;
         return HashKey::from(h as u32);
     }
@@ -549,7 +801,7 @@ impl MXF64 {
     fn hash(&self, key: HashKey) -> HashKey {
         let mut h: u64 = (key.as_u64()).wrapping_mul(self.seed.as_u64() | 1);
         h ^= h >> 33;
-        h = h.wrapping_mul(0xff51afd7ed558ccd as u64);
+        h = h.wrapping_mul(0xff51afd7ed558ccd_u64);
         h ^= h >> 33;
         return HashKey::from(h);
     }
@@ -569,7 +821,7 @@ impl Default for XorShift {
     fn default() -> Self {
         return Self {
             name: "xorshift".to_string(),
-            seed: HashSeed::from(0 as u32),
+            seed: HashSeed::from(0_u32),
         };
     }
 }
@@ -615,13 +867,22 @@ impl FromStr for SOHash {
             "mxf64" => Ok(SOHash::MXF64(MXF64::default())),
             "xorshift" => Ok(SOHash::XorShift(XorShift::default())),
             _ => {
-                Err("Cannot find a corresponding second-order hash. Expected: mxf, xorshift".into())
+                Err("Cannot find a corresponding second-order hash. Expected: mxf, mxf64, xorshift")
             }
         }
     }
 }
 
 impl SOHash {
+    /// Every registered second-order hash, in registration order
+    pub fn all() -> Vec<SOHash> {
+        return vec![
+            SOHash::MXF(MXF::default()),
+            SOHash::MXF64(MXF64::default()),
+            SOHash::XorShift(XorShift::default()),
+        ];
+    }
+
     pub fn hash(&self, key: HashKey) -> HashKey {
         match self {
             SOHash::MXF(x) => x.hash(key),
@@ -661,4 +922,13 @@ impl SOHash {
             SOHash::XorShift(_) => false,
         }
     }
+
+    /// Rough number of arithmetic operations of one evaluation, see `FOHash::cost`
+    pub fn cost(&self) -> f64 {
+        match self {
+            SOHash::MXF(_) => 6.0,
+            SOHash::MXF64(_) => 6.0,
+            SOHash::XorShift(_) => 13.0,
+        }
+    }
 }
@@ -0,0 +1,883 @@
+// https://www.rfc-editor.org/rfc/rfc4180
+// https://jsonlines.org
+
+use super::gperf::Gperf;
+use super::normalize::Normalization;
+use super::phash::ItemType;
+use std::io::Read;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::{Chars, FromStr};
+
+/// Column of a delimited file, by position or by name in the header
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    /// Columns are numbered from 1, anything else is a header name
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<usize>() {
+            Ok(0) => Err("Invalid column 0, columns are numbered from 1".to_string()),
+            Ok(n) => Ok(Column::Index(n - 1)),
+            Err(_) => Ok(Column::Name(s.to_string())),
+        }
+    }
+}
+
+/// Input file read in memory, - being the standard input
+#[derive(Debug, Clone)]
+pub struct InputFile {
+    pub path: PathBuf,
+    /// Content of the file. Files that are not valid UTF-8 are decoded a byte per character
+    /// (Latin-1), so that the bytes of their keys are read back as they are
+    pub content: String,
+    /// Offset of the first byte that is not valid UTF-8, if any
+    pub invalid_utf8: Option<usize>,
+    /// Declarations and keywords of gperf files, parsed once when the file is read
+    pub gperf: Option<Gperf>,
+}
+
+impl InputFile {
+    pub fn is_stdin(path: &Path) -> bool {
+        return path == Path::new("-");
+    }
+
+    pub fn read(path: &Path) -> Result<InputFile, String> {
+        let bytes = match InputFile::is_stdin(path) {
+            true => {
+                let mut bytes = Vec::new();
+
+                std::io::stdin()
+                    .read_to_end(&mut bytes)
+                    .map_err(|e| format!("Cannot read the standard input: {e}"))?;
+
+                bytes
+            }
+            false => std::fs::read(path).map_err(|e| format!("Cannot read file {}: {}", path.display(), e))?,
+        };
+
+        let (content, invalid_utf8) = match String::from_utf8(bytes) {
+            Ok(content) => (content, None),
+            Err(e) => {
+                let invalid_utf8 = e.utf8_error().valid_up_to();
+                (e.into_bytes().into_iter().map(char::from).collect(), Some(invalid_utf8))
+            }
+        };
+
+        let mut file = InputFile {
+            path: path.to_path_buf(),
+            content,
+            invalid_utf8,
+            gperf: None,
+        };
+
+        if Gperf::is_gperf(path) {
+            file.gperf = Some(Gperf::read(&file).map_err(|e| e.to_string())?);
+        }
+
+        return Ok(file);
+    }
+
+    /// Name of the file in messages
+    pub fn name(&self) -> String {
+        match InputFile::is_stdin(&self.path) {
+            true => return "<stdin>".to_string(),
+            false => return self.path.display().to_string(),
+        }
+    }
+
+    /// Content of files read as text, the error gives the line and column of the first byte
+    /// that is not valid UTF-8
+    pub fn text(&self) -> Result<&str, String> {
+        let Some(offset) = self.invalid_utf8 else {
+            return Ok(&self.content);
+        };
+
+        let before = &self.content[..self.content.char_indices().nth(offset).map_or(self.content.len(), |(i, _)| i)];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+
+        return Err(format!("{line}:{column}: invalid UTF-8, only delimited files may hold binary keys"));
+    }
+
+    /// Bytes of a text of the file, as they are in the file
+    pub fn bytes_of(&self, text: &str) -> Vec<u8> {
+        match self.invalid_utf8 {
+            Some(_) => return text.chars().map(|c| c as u8).collect(),
+            None => return text.as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Paths of the input files, in order: - for the standard input, paths, and glob patterns
+/// expanded in alphabetical order
+pub fn expand_files(patterns: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::new();
+
+    for pattern in patterns.iter() {
+        let path = PathBuf::from(pattern);
+
+        if InputFile::is_stdin(&path) && paths.iter().any(|other: &PathBuf| InputFile::is_stdin(other)) {
+            return Err("The standard input (-) can only be read once".to_string());
+        }
+
+        if InputFile::is_stdin(&path) || path.exists() {
+            paths.push(path);
+            continue;
+        }
+
+        if !pattern.contains(['*', '?', '[']) {
+            return Err(format!("Cannot find file {pattern}"));
+        }
+
+        let matches = glob::glob(pattern)
+            .map_err(|e| format!("Invalid pattern {pattern}: {e}"))?
+            .filter_map(|entry| entry.ok())
+            .filter(|path| path.is_file())
+            .collect::<Vec<PathBuf>>();
+
+        if matches.is_empty() {
+            return Err(format!("Cannot find any file matching {pattern}"));
+        }
+
+        paths.extend(matches);
+    }
+
+    return Ok(paths);
+}
+
+/// Format of an input file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Delimited records, comma or tab separated
+    Csv,
+    /// Array of keys or records, or object mapping keys to values
+    Json,
+    /// Key or record per line
+    Jsonl,
+}
+
+impl FromStr for InputFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(InputFormat::Csv),
+            "json" => Ok(InputFormat::Json),
+            "jsonl" => Ok(InputFormat::Jsonl),
+            _ => Err("Cannot find a corresponding input format. Expected: csv, json, jsonl"),
+        }
+    }
+}
+
+/// Encoding of the keys of input files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyEncoding {
+    /// The bytes of the key, as they are
+    #[default]
+    Raw,
+    /// Bytes written with \xNN, \\, \n, \r, \t and \0 escapes
+    Escaped,
+    Hex,
+    Base64,
+}
+
+impl FromStr for KeyEncoding {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(KeyEncoding::Raw),
+            "escaped" => Ok(KeyEncoding::Escaped),
+            "hex" => Ok(KeyEncoding::Hex),
+            "base64" => Ok(KeyEncoding::Base64),
+            _ => Err("Cannot find a corresponding key encoding. Expected: raw, escaped, hex, base64"),
+        }
+    }
+}
+
+impl KeyEncoding {
+    /// Bytes of a key of an input file
+    pub fn decode(&self, text: &str, file: &InputFile) -> Result<Vec<u8>, String> {
+        match self {
+            KeyEncoding::Raw => return Ok(file.bytes_of(text)),
+            KeyEncoding::Escaped => return unescape_bytes(&file.bytes_of(text)),
+            KeyEncoding::Hex => return decode_hex(text.trim()),
+            KeyEncoding::Base64 => return decode_base64(text.trim()),
+        }
+    }
+}
+
+/// Layout of input files
+#[derive(Debug, Clone, Default)]
+pub struct InputOptions {
+    /// Format of the file, from its extension if None
+    pub format: Option<InputFormat>,
+    pub key_encoding: KeyEncoding,
+    /// Transforms applied to the keys once decoded
+    pub normalization: Normalization,
+    /// Separator of the fields, a comma by default and a tab for .tsv files
+    pub delimiter: Option<char>,
+    /// Character taking the next one literally, in and out of quoted fields
+    pub escape: Option<char>,
+    /// The first record names the columns
+    pub header: bool,
+    /// Column of the keys, every field is a key if None. Field of the keys of JSON records,
+    /// \"key\" or the first one if None
+    pub key_column: Option<Column>,
+    /// Column of the values of retrieval tables, the one after the key column if None. Field
+    /// of the values of JSON records, \"value\" or the second one if None
+    pub value_column: Option<Column>,
+}
+
+impl InputOptions {
+    pub fn format_of(&self, path: &Path) -> InputFormat {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+
+        return self.format.unwrap_or(match extension {
+            Some("json") => InputFormat::Json,
+            Some("jsonl" | "ndjson") => InputFormat::Jsonl,
+            _ => InputFormat::Csv,
+        });
+    }
+
+    pub fn delimiter_of(&self, path: &Path) -> char {
+        let is_tsv = path.extension().is_some_and(|extension| extension == "tsv");
+
+        return self.delimiter.unwrap_or(if is_tsv { '\t' } else { ',' });
+    }
+}
+
+/// Field of a record, with its position in the file for error messages
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub value: String,
+    /// Quoted fields may be empty, unquoted empty fields are most likely a mistake
+    pub quoted: bool,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Records of a delimited file, with the columns named by its header
+#[derive(Debug, Clone, Default)]
+pub struct Records {
+    pub header: Vec<String>,
+    pub records: Vec<Vec<Field>>,
+}
+
+impl Records {
+    pub fn parse(content: &str, options: &InputOptions, delimiter: char) -> Result<Records, String> {
+        let mut records = parse_records(content, delimiter, options.escape)?;
+
+        let header = match options.header && !records.is_empty() {
+            true => records.remove(0).into_iter().map(|field| field.value).collect(),
+            false => Vec::new(),
+        };
+
+        return Ok(Records { header, records });
+    }
+
+    /// Position of a column in the records
+    pub fn index_of(&self, column: &Column) -> Result<usize, String> {
+        match column {
+            Column::Index(index) => Ok(*index),
+            Column::Name(name) => self.header.iter().position(|other| other == name).ok_or_else(|| {
+                match self.header.is_empty() {
+                    true => format!("Cannot find column \"{name}\", columns are only named with a header row"),
+                    false => format!("Cannot find column \"{name}\" in the header: {}", self.header.join(", ")),
+                }
+            }),
+        }
+    }
+
+    /// Field of a record at a column, the error gives the line of the record
+    pub fn field(record: &[Field], index: usize) -> Result<&Field, String> {
+        return record.get(index).ok_or_else(|| {
+            let line = record.first().map_or(0, |field| field.line);
+            format!("{line}: missing column {}, the record has {} field(s)", index + 1, record.len())
+        });
+    }
+}
+
+// Decodes the \xNN, \\, \n, \r, \t and \0 escapes of a key
+fn unescape_bytes(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+
+        let escaped = match bytes.get(i + 1) {
+            Some(b'\\') => b'\\',
+            Some(b'n') => b'\n',
+            Some(b'r') => b'\r',
+            Some(b't') => b'\t',
+            Some(b'0') => 0,
+            Some(b'x') => {
+                let digits = bytes.get(i + 2..i + 4).and_then(|digits| std::str::from_utf8(digits).ok());
+
+                let byte = digits
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .ok_or_else(|| "invalid \\x escape, expected two hexadecimal digits".to_string())?;
+
+                decoded.push(byte);
+                i += 4;
+                continue;
+            }
+            Some(&other) => return Err(format!("unknown escape \\{}", other.escape_ascii())),
+            None => return Err("escape at the end of the key".to_string()),
+        };
+
+        decoded.push(escaped);
+        i += 2;
+    }
+
+    return Ok(decoded);
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>, String> {
+    if !text.len().is_multiple_of(2) {
+        return Err(format!("invalid hexadecimal key \"{text}\", odd number of digits"));
+    }
+
+    return (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| format!("invalid hexadecimal key \"{text}\""))
+        })
+        .collect();
+}
+
+// Standard alphabet, the padding is optional
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let invalid = || format!("invalid base64 key \"{text}\"");
+
+    let sextets = text
+        .trim_end_matches('=')
+        .bytes()
+        .map(|c| match c {
+            b'A'..=b'Z' => Ok(c - b'A'),
+            b'a'..=b'z' => Ok(c - b'a' + 26),
+            b'0'..=b'9' => Ok(c - b'0' + 52),
+            b'+' => Ok(62),
+            b'/' => Ok(63),
+            _ => Err(invalid()),
+        })
+        .collect::<Result<Vec<u8>, String>>()?;
+
+    // A single character of a group of four holds less than a byte
+    if sextets.len() % 4 == 1 {
+        return Err(invalid());
+    }
+
+    let mut decoded = Vec::with_capacity(sextets.len() * 3 / 4);
+
+    for group in sextets.chunks(4) {
+        let bits = group.iter().enumerate().fold(0_u32, |bits, (i, &sextet)| bits | (sextet as u32) << (18 - 6 * i));
+        decoded.extend_from_slice(&bits.to_be_bytes()[1..group.len()]);
+    }
+
+    return Ok(decoded);
+}
+
+/// Parses a delimiter option: a single character, or tab
+pub fn parse_delimiter(s: &str) -> Result<char, String> {
+    if s == "tab" || s == "\\t" {
+        return Ok('\t');
+    }
+
+    let mut chars = s.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if !matches!(c, '"' | '\n' | '\r') => Ok(c),
+        _ => Err(format!("Invalid delimiter \"{s}\", expected a single character other than a quote or a line break, or tab")),
+    }
+}
+
+// Reads characters while keeping track of their line and column
+struct Cursor<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl Cursor<'_> {
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        return Some(c);
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        return self.chars.peek().copied();
+    }
+
+    fn at_line_break(&self) -> bool {
+        let mut lookahead = self.chars.clone();
+
+        match lookahead.next() {
+            Some('\n') => return true,
+            Some('\r') => return lookahead.next() == Some('\n'),
+            _ => return false,
+        }
+    }
+
+    // Consumes a line break, \n or \r\n
+    fn line_break(&mut self) {
+        if self.peek() == Some('\r') {
+            self.chars.next();
+        }
+
+        self.next();
+    }
+}
+
+/// Splits delimited content into records: quoted fields may hold delimiters, line breaks and
+/// doubled quotes, lines end with \n or \r\n and empty lines are skipped. Errors start with the
+/// line and column they occur on
+pub fn parse_records(content: &str, delimiter: char, escape: Option<char>) -> Result<Vec<Vec<Field>>, String> {
+    let mut cursor = Cursor {
+        chars: content.chars().peekable(),
+        line: 1,
+        column: 1,
+    };
+
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+
+    loop {
+        if record.is_empty() && cursor.peek().is_none() {
+            break;
+        }
+
+        let (line, column) = (cursor.line, cursor.column);
+        let mut value = String::new();
+        let quoted = cursor.peek() == Some('"');
+
+        if quoted {
+            cursor.next();
+
+            loop {
+                match cursor.next() {
+                    None => return Err(format!("{line}:{column}: quoted field without a closing quote")),
+                    Some(c) if Some(c) == escape => match cursor.next() {
+                        Some(escaped) => value.push(escaped),
+                        None => return Err(format!("{}:{}: escape at the end of the file", cursor.line, cursor.column)),
+                    },
+                    Some('"') if cursor.peek() == Some('"') => {
+                        cursor.next();
+                        value.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => value.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = cursor.peek() {
+                if c == delimiter || cursor.at_line_break() {
+                    break;
+                }
+
+                if c == '"' {
+                    return Err(format!(
+                        "{}:{}: quote in an unquoted field, quote the whole field and double the quotes in it",
+                        cursor.line, cursor.column
+                    ));
+                }
+
+                cursor.next();
+
+                if Some(c) == escape {
+                    match cursor.next() {
+                        Some(escaped) => value.push(escaped),
+                        None => return Err(format!("{}:{}: escape at the end of the file", cursor.line, cursor.column)),
+                    }
+                } else {
+                    value.push(c);
+                }
+            }
+        }
+
+        record.push(Field { value, quoted, line, column });
+
+        if cursor.peek() == Some(delimiter) {
+            cursor.next();
+            continue;
+        }
+
+        if cursor.peek().is_some() && !cursor.at_line_break() {
+            return Err(format!(
+                "{}:{}: expected a delimiter or a line break after the closing quote",
+                cursor.line, cursor.column
+            ));
+        }
+
+        cursor.line_break();
+
+        // Empty lines hold a single empty field
+        let is_empty_line = record.len() == 1 && !record[0].quoted && record[0].value.is_empty();
+
+        if is_empty_line {
+            record.clear();
+        } else {
+            records.push(std::mem::take(&mut record));
+        }
+    }
+
+    return Ok(records);
+}
+
+/// Value of a key, carried into the generated tables
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    /// Array of scalars
+    Array(Vec<Value>),
+}
+
+/// Type of the values of a keyset, the values of all the keys share it
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueType {
+    Bool,
+    Int,
+    Float,
+    Str,
+    /// Type of the elements, None while the arrays are empty
+    Array(Option<Box<ValueType>>),
+}
+
+impl Value {
+    pub fn from_json(json: &serde_json::Value) -> Result<Value, String> {
+        match json {
+            serde_json::Value::Bool(b) => Ok(Value::Bool(*b)),
+            serde_json::Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+                (Some(i), _) => Ok(Value::Int(i)),
+                (None, _) if n.is_u64() => Err(format!("value {n} is out of the range of 64-bit signed integers")),
+                (None, Some(f)) => Ok(Value::Float(f)),
+                (None, None) => Err(format!("invalid number {n}")),
+            },
+            serde_json::Value::String(s) => Ok(Value::Str(s.clone())),
+            serde_json::Value::Array(elements) => {
+                let mut values = Vec::with_capacity(elements.len());
+
+                for element in elements.iter() {
+                    match Value::from_json(element)? {
+                        Value::Array(_) => return Err("nested arrays are not supported, only arrays of scalars".to_string()),
+                        value => values.push(value),
+                    }
+                }
+
+                let array = Value::Array(values);
+                array.value_type()?;
+
+                Ok(array)
+            }
+            serde_json::Value::Null => Err("null values are not supported".to_string()),
+            serde_json::Value::Object(_) => Err("object values are not supported, only scalars and arrays of scalars".to_string()),
+        }
+    }
+
+    pub fn value_type(&self) -> Result<ValueType, String> {
+        match self {
+            Value::Bool(_) => Ok(ValueType::Bool),
+            Value::Int(_) => Ok(ValueType::Int),
+            Value::Float(_) => Ok(ValueType::Float),
+            Value::Str(_) => Ok(ValueType::Str),
+            Value::Array(values) => {
+                let mut element_type: Option<ValueType> = None;
+
+                for value in values.iter() {
+                    let other = value.value_type()?;
+
+                    element_type = Some(match element_type {
+                        Some(element_type) => element_type.unify(&other).ok_or_else(|| {
+                            format!("array mixing {} and {} elements", element_type.name(), other.name())
+                        })?,
+                        None => other,
+                    });
+                }
+
+                Ok(ValueType::Array(element_type.map(Box::new)))
+            }
+        }
+    }
+}
+
+impl ValueType {
+    pub fn name(&self) -> String {
+        match self {
+            ValueType::Bool => "bool".to_string(),
+            ValueType::Int => "int".to_string(),
+            ValueType::Float => "float".to_string(),
+            ValueType::Str => "string".to_string(),
+            ValueType::Array(Some(element_type)) => format!("array of {}", element_type.name()),
+            ValueType::Array(None) => "empty array".to_string(),
+        }
+    }
+
+    /// Type holding the values of both types. Integers are not widened to floats, which would
+    /// round the large ones
+    pub fn unify(&self, other: &ValueType) -> Option<ValueType> {
+        match (self, other) {
+            (ValueType::Array(None), ValueType::Array(element_type))
+            | (ValueType::Array(element_type), ValueType::Array(None)) => Some(ValueType::Array(element_type.clone())),
+            (ValueType::Array(Some(a)), ValueType::Array(Some(b))) => Some(ValueType::Array(Some(Box::new(a.unify(b)?)))),
+            (a, b) if a == b => Some(a.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Values of the keys of an input file, in input order. Duplicate keys keep their first value
+#[derive(Debug, Clone)]
+pub struct Values {
+    pub value_type: ValueType,
+    pub keys: Vec<ItemType>,
+    pub values: Vec<Value>,
+}
+
+/// Key of a JSON or JSON Lines file, with its value if the record has one
+#[derive(Debug, Clone)]
+pub struct JsonRecord {
+    pub key: String,
+    pub value: Option<Value>,
+    /// Line of JSON Lines records, index or key of JSON ones, for error messages
+    pub location: String,
+}
+
+/// Parses a JSON document: an array of keys or records, or an object mapping keys to values
+pub fn parse_json(content: &str, options: &InputOptions) -> Result<Vec<JsonRecord>, String> {
+    let document: serde_json::Value = serde_json::from_str(content).map_err(|e| json_error(&e, e.line()))?;
+
+    match document {
+        serde_json::Value::Array(elements) => {
+            return elements
+                .iter()
+                .enumerate()
+                .map(|(i, element)| {
+                    let location = format!("[{i}]");
+                    let (key, value) = json_record(element, options).map_err(|e| format!("{location}: {e}"))?;
+
+                    Ok(JsonRecord { key, value, location })
+                })
+                .collect();
+        }
+        serde_json::Value::Object(fields) => {
+            return fields
+                .iter()
+                .map(|(key, value)| {
+                    let location = format!("[\"{key}\"]");
+                    let value = Value::from_json(value).map_err(|e| format!("{location}: {e}"))?;
+
+                    Ok(JsonRecord {
+                        key: key.clone(),
+                        value: Some(value),
+                        location,
+                    })
+                })
+                .collect();
+        }
+        _ => return Err("expected an array of keys or records, or an object mapping keys to values".to_string()),
+    }
+}
+
+/// Parses JSON Lines, a key or a record per line. Empty lines are skipped
+pub fn parse_jsonl(content: &str, options: &InputOptions) -> Result<Vec<JsonRecord>, String> {
+    let mut records = Vec::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let json: serde_json::Value = serde_json::from_str(line).map_err(|e| json_error(&e, i + 1))?;
+
+        let location = format!("{}", i + 1);
+        let (key, value) = json_record(&json, options).map_err(|e| format!("{location}: {e}"))?;
+
+        records.push(JsonRecord { key, value, location });
+    }
+
+    return Ok(records);
+}
+
+// Error of serde_json at the given line, without the position it appends to its message
+fn json_error(e: &serde_json::Error, line: usize) -> String {
+    let message = e.to_string();
+    let message = message.rsplit_once(" at line ").map_or(message.as_str(), |(message, _)| message);
+
+    return format!("{line}:{}: {message}", e.column());
+}
+
+// Key and value of a record: a key, an object with key and value fields, or an array
+fn json_record(json: &serde_json::Value, options: &InputOptions) -> Result<(String, Option<Value>), String> {
+    let (key, value) = match json {
+        serde_json::Value::Object(fields) => {
+            let field_name = |column: &Option<Column>, default: &'static str| match column {
+                Some(Column::Name(name)) => Ok(name.clone()),
+                Some(Column::Index(_)) => Err("the records are objects, their fields are named".to_string()),
+                None => Ok(default.to_string()),
+            };
+
+            let key_field = field_name(&options.key_column, "key")?;
+            let value_field = field_name(&options.value_column, "value")?;
+
+            let key = fields.get(&key_field).ok_or_else(|| format!("missing key field \"{key_field}\""))?;
+
+            (key, fields.get(&value_field))
+        }
+        serde_json::Value::Array(fields) => {
+            let field_index = |column: &Option<Column>, default: usize| match column {
+                Some(Column::Index(index)) => Ok(*index),
+                Some(Column::Name(_)) => Err("the records are arrays, their fields are numbered".to_string()),
+                None => Ok(default),
+            };
+
+            let key_index = field_index(&options.key_column, 0)?;
+            let value_index = field_index(&options.value_column, key_index + 1)?;
+
+            let key = fields
+                .get(key_index)
+                .ok_or_else(|| format!("missing field {}, the record has {} field(s)", key_index + 1, fields.len()))?;
+
+            (key, fields.get(value_index))
+        }
+        key => (key, None),
+    };
+
+    let key = match key {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Number(n) => n.to_string(),
+        other => return Err(format!("expected a string or a number as key, found {other}")),
+    };
+
+    return Ok((key, value.map(Value::from_json).transpose()?));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Values of the records, quoted fields between quotes
+    fn values(content: &str, escape: Option<char>) -> Vec<Vec<String>> {
+        return parse_records(content, ',', escape)
+            .unwrap()
+            .iter()
+            .map(|record| {
+                record
+                    .iter()
+                    .map(|field| match field.quoted {
+                        true => format!("\"{}\"", field.value),
+                        false => field.value.clone(),
+                    })
+                    .collect()
+            })
+            .collect();
+    }
+
+    #[test]
+    fn records_are_split_into_fields() {
+        type Case<'a> = (&'a str, &'a str, Option<char>, &'a [&'a [&'a str]]);
+
+        let cases: [Case; 11] = [
+            ("empty file", "", None, &[]),
+            ("single field", "a", None, &[&["a"]]),
+            ("fields and records", "a,b\nc,d\n", None, &[&["a", "b"], &["c", "d"]]),
+            ("empty fields", "a,,b,", None, &[&["a", "", "b", ""]]),
+            ("empty lines", "\na\n\n\nb\n\n", None, &[&["a"], &["b"]]),
+            ("crlf", "a,b\r\nc\r\n", None, &[&["a", "b"], &["c"]]),
+            ("lone cr", "a\rb\n", None, &[&["a\rb"]]),
+            ("quoted delimiter and line breaks", "\"a,b\",\"c\r\nd\"\n", None, &[&["\"a,b\"", "\"c\r\nd\""]]),
+            ("doubled quotes", "\"say \"\"hi\"\"\",\"\"\"\"", None, &[&["\"say \"hi\"\"", "\"\"\""]]),
+            ("quoted empty field", "\"\",a", None, &[&["\"\"", "a"]]),
+            ("escapes", "a\\,b,\"c\\\"d\"", Some('\\'), &[&["a,b", "\"c\"d\""]]),
+        ];
+
+        for (name, content, escape, expected) in cases {
+            let expected: Vec<Vec<String>> = expected.iter().map(|record| record.iter().map(|value| value.to_string()).collect()).collect();
+            assert_eq!(values(content, escape), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn fields_know_their_line_and_column() {
+        let records = parse_records("a,\"b\nc\",d\r\n\nee,f", ',', None).unwrap();
+        let positions: Vec<Vec<(usize, usize)>> =
+            records.iter().map(|record| record.iter().map(|field| (field.line, field.column)).collect()).collect();
+
+        assert_eq!(positions, vec![vec![(1, 1), (1, 3), (2, 4)], vec![(4, 1), (4, 4)]]);
+    }
+
+    #[test]
+    fn errors_start_with_their_line_and_column() {
+        let cases = [
+            ("unclosed quote", "a\n  b,\"c", None, "2:5: quoted field without a closing quote"),
+            ("quote in unquoted field", "a,b\"c", None, "1:4: quote in an unquoted field"),
+            ("text after closing quote", "\"a\"b", None, "1:4: expected a delimiter or a line break"),
+            ("escape at the end", "a\\", Some('\\'), "1:3: escape at the end of the file"),
+        ];
+
+        for (name, content, escape, expected) in cases {
+            let error = parse_records(content, ',', escape).unwrap_err();
+            assert!(error.starts_with(expected), "{name}: {error}");
+        }
+    }
+
+    #[test]
+    fn header_row_names_the_columns() {
+        let options = InputOptions {
+            header: true,
+            ..InputOptions::default()
+        };
+
+        let records = Records::parse("key;value\nalpha;1\nbravo;2\n", &options, ';').unwrap();
+
+        assert_eq!(records.header, vec!["key", "value"]);
+        assert_eq!(records.records.len(), 2);
+        assert_eq!(records.index_of(&Column::Name("value".to_string())), Ok(1));
+        assert!(records.index_of(&Column::Name("other".to_string())).unwrap_err().contains("key, value"));
+
+        let records = Records::parse("alpha;1\n", &InputOptions::default(), ';').unwrap();
+
+        assert!(records.header.is_empty());
+        assert_eq!(records.records.len(), 1);
+        assert!(records.index_of(&Column::Name("key".to_string())).unwrap_err().contains("header row"));
+    }
+
+    #[test]
+    fn integers_and_floats_are_not_unified() {
+        assert_eq!(ValueType::Int.unify(&ValueType::Float), None);
+        assert_eq!(ValueType::Float.unify(&ValueType::Int), None);
+        assert_eq!(ValueType::Float.unify(&ValueType::Float), Some(ValueType::Float));
+
+        let floats = ValueType::Array(Some(Box::new(ValueType::Float)));
+        assert_eq!(ValueType::Array(None).unify(&floats), Some(floats.clone()));
+        assert_eq!(ValueType::Array(Some(Box::new(ValueType::Int))).unify(&floats), None);
+
+        let error = parse_jsonl("{\"key\": \"a\", \"value\": [9007199254740993, 0.5]}\n", &InputOptions::default()).unwrap_err();
+        assert_eq!(error, "1: array mixing int and float elements");
+    }
+}
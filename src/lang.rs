@@ -3,9 +3,12 @@ use once_cell::sync::Lazy;
 use serde::Deserialize;
 use std::any::type_name;
 use std::collections::HashMap;
+use std::fmt::Display;
 use std::fs;
 use std::path::PathBuf;
 
+use super::input::{Value, ValueType};
+use super::normalize::{self, Case, Normalization};
 use super::phash::ItemType;
 
 fn get_exe_dir() -> PathBuf {
@@ -62,12 +65,16 @@ static SO_HASHES: Lazy<SOHashConfig> = Lazy::new(|| {
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct GetData {
+    /// Code shared by the lookups rendered from the template, written once before them
+    pub helpers: Option<String>,
     pub body: String,
+    /// Slot of the value matching the key, returned by the index lookups
+    pub index: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
 struct GetConfig {
-    functions: HashMap<String, GetData>,
+    functions: HashMap<String, HashMap<String, GetData>>,
 }
 
 static GETS: Lazy<GetConfig> = Lazy::new(|| {
@@ -96,11 +103,11 @@ impl From<&str> for OutputLang {
     }
 }
 
-impl ToString for OutputLang {
-    fn to_string(&self) -> String {
+impl Display for OutputLang {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            OutputLang::C => "c".to_string(),
-            OutputLang::Python => "py".to_string(),
+            OutputLang::C => write!(f, "c"),
+            OutputLang::Python => write!(f, "py"),
         }
     }
 }
@@ -124,6 +131,35 @@ impl OutputLang {
         }
     }
 
+    /// Smallest unsigned integer type holding values of the given number of bits
+    pub fn map_uint(&self, bits: u32) -> &'static str {
+        match self {
+            OutputLang::C => match bits {
+                0..=8 => "unsigned char",
+                9..=16 => "unsigned short",
+                17..=32 => "unsigned int",
+                _ => "unsigned long long",
+            },
+            OutputLang::Python => "int",
+        }
+    }
+
+    pub fn get_seed_literal(&self, seed: u64, is_64bits: bool) -> String {
+        if is_64bits {
+            return self.get_word_literal(seed);
+        }
+
+        return format!("{seed:#x}");
+    }
+
+    /// 64-bits word of a packed bit array
+    pub fn get_word_literal(&self, word: u64) -> String {
+        match self {
+            OutputLang::C => format!("0x{word:016x}ULL"),
+            OutputLang::Python => format!("0x{word:016x}"),
+        }
+    }
+
     pub fn map_type<T>(&self, _: &T) -> &'static str {
         match self {
             OutputLang::C => match type_name::<T>() {
@@ -157,6 +193,7 @@ impl OutputLang {
         match self {
             OutputLang::C => match t {
                 ItemType::Str(_) => "char*",
+                ItemType::Bytes(_) => "char*",
                 ItemType::I32(_) => "int",
                 ItemType::I64(_) => "long long",
                 ItemType::U32(_) => "unsigned int",
@@ -164,6 +201,7 @@ impl OutputLang {
             },
             OutputLang::Python => match t {
                 ItemType::Str(_) => "str",
+                ItemType::Bytes(_) => "bytes",
                 ItemType::I32(_) => "int",
                 ItemType::I64(_) => "int",
                 ItemType::U32(_) => "int",
@@ -172,10 +210,64 @@ impl OutputLang {
         }
     }
 
+    /// Parameters of the lookups, C byte strings may hold null bytes and are passed with their
+    /// length
+    pub fn get_key_params(&self, t: &ItemType) -> String {
+        match self {
+            OutputLang::C => match t {
+                ItemType::Bytes(_) => "const void* key, size_t len".to_string(),
+                _ => format!("const {} key", self.get_type(t)),
+            },
+            OutputLang::Python => format!("key: {}", self.get_type(t)),
+        }
+    }
+
+    /// Arguments of a lookup called with a key and its size
+    pub fn get_key_arguments(&self, t: &ItemType, key_name: &str, size_name: &str) -> String {
+        match (self, t) {
+            (OutputLang::C, ItemType::Bytes(_)) => format!("{key_name}, {size_name}"),
+            _ => key_name.to_string(),
+        }
+    }
+
+    /// Return type of the lookup, which returns the stored key or the miss value. Integers are
+    /// returned by value, a const qualifier would be ignored
+    pub fn get_return_type(&self, t: &ItemType) -> String {
+        match self {
+            OutputLang::C => match t {
+                ItemType::Str(_) | ItemType::Bytes(_) => format!("const {}", self.get_type(t)),
+                _ => self.get_type(t).to_string(),
+            },
+            OutputLang::Python => format!("{} | None", self.get_type(t)),
+        }
+    }
+
+    /// Type of the index returned by the index lookups, -1 when the key is missing
+    pub fn get_index_type(&self) -> &str {
+        match self {
+            OutputLang::C => "long long",
+            OutputLang::Python => "int",
+        }
+    }
+
+    pub fn get_bool_type(&self) -> &str {
+        match self {
+            OutputLang::C => "int",
+            OutputLang::Python => "bool",
+        }
+    }
+
+    pub fn get_bool_literal(&self, value: bool) -> &str {
+        match self {
+            OutputLang::C => if value { "1" } else { "0" },
+            OutputLang::Python => if value { "True" } else { "False" },
+        }
+    }
+
     pub fn get_imports_from_type(&self, t: &ItemType) -> Option<String> {
         match self {
             OutputLang::C => match t {
-                ItemType::Str(_) => Some("#include <string.h>\n".to_string()),
+                ItemType::Str(_) | ItemType::Bytes(_) => Some("#include <string.h>\n".to_string()),
                 _ => None,
             },
             _ => None,
@@ -185,7 +277,7 @@ impl OutputLang {
     pub fn get_imports_for_test(&self, t: &ItemType) -> Option<String> {
         match self {
             OutputLang::C => match t {
-                ItemType::Str(_) => Some("#include <assert.h>\n".to_string()),
+                ItemType::Str(_) | ItemType::Bytes(_) => Some("#include <assert.h>\n".to_string()),
                 _ => None,
             },
             _ => None,
@@ -224,6 +316,7 @@ impl OutputLang {
         match self {
             OutputLang::C => match t {
                 ItemType::Str(_) => "",
+                ItemType::Bytes(_) => "",
                 ItemType::I32(_) => "&",
                 ItemType::I64(_) => "&",
                 ItemType::U32(_) => "&",
@@ -237,10 +330,11 @@ impl OutputLang {
         match self {
             OutputLang::C => match t {
                 ItemType::Str(_) => format!("strlen({key_name})"),
+                ItemType::Bytes(_) => "len".to_string(),
                 ItemType::I32(_) => "sizeof(int)".to_string(),
-                ItemType::I64(_) => "sizeof(long int)".to_string(),
+                ItemType::I64(_) => "sizeof(long long)".to_string(),
                 ItemType::U32(_) => "sizeof(unsigned int)".to_string(),
-                ItemType::U64(_) => "sizeof(unsigned long int)".to_string(),
+                ItemType::U64(_) => "sizeof(unsigned long long)".to_string(),
             },
             _ => "".to_string(),
         }
@@ -249,7 +343,7 @@ impl OutputLang {
     pub fn get_key_conversion_start(&self, t: &ItemType) -> &str {
         match self {
             OutputLang::Python => match t {
-                ItemType::Str(_) => "bytes(",
+                ItemType::Str(_) | ItemType::Bytes(_) => "bytes(",
                 _ => "",
             },
             _ => "",
@@ -260,15 +354,401 @@ impl OutputLang {
         match self {
             OutputLang::Python => match t {
                 ItemType::Str(_) => ".encode(errors=\"replace\"))",
-                ItemType::I32(_) => "to_bytes(4)",
-                ItemType::I64(_) => "to_bytes(8)",
-                ItemType::U32(_) => "to_bytes(4)",
-                ItemType::U64(_) => "to_bytes(8)",
+                ItemType::Bytes(_) => ")",
+                ItemType::I32(_) => ".to_bytes(4, \"little\", signed=True)",
+                ItemType::I64(_) => ".to_bytes(8, \"little\", signed=True)",
+                ItemType::U32(_) => ".to_bytes(4, \"little\")",
+                ItemType::U64(_) => ".to_bytes(8, \"little\")",
             },
             _ => "",
         }
     }
 
+    /// Comparisons of keys, C byte strings are compared with their sizes
+    pub fn get_key_equals(&self, t: &ItemType, a: &str, a_size: &str, b: &str, b_size: &str) -> String {
+        match self {
+            OutputLang::C => match t {
+                ItemType::Str(_) => format!("strcmp({a}, {b}) == 0"),
+                ItemType::Bytes(_) => format!("{a_size} == {b_size} && memcmp({a}, {b}, {b_size}) == 0"),
+                _ => format!("{a} == {b}"),
+            },
+            OutputLang::Python => format!("{a} == {b}"),
+        }
+    }
+
+    /// Negative, zero or positive when a sorts before, with or after b. C byte strings are
+    /// compared by the compare function
+    pub fn get_key_compare(&self, t: &ItemType, a: &str, a_size: &str, b: &str, b_size: &str, compare_name: &str) -> String {
+        match self {
+            OutputLang::C => match t {
+                ItemType::Str(_) => format!("strcmp({a}, {b})"),
+                ItemType::Bytes(_) => format!("{compare_name}({a}, {a_size}, {b}, {b_size})"),
+                _ => format!("({a} > {b}) - ({a} < {b})"),
+            },
+            OutputLang::Python => format!("({a} > {b}) - ({a} < {b})"),
+        }
+    }
+
+    /// Key of the switch case of a key, its length and first character for strings
+    pub fn get_case_key(&self, t: &ItemType, key_name: &str) -> String {
+        match self {
+            OutputLang::Python => match t {
+                ItemType::Str(_) => format!("(len({key_name}), ord({key_name}[0]) if {key_name} else 0)"),
+                ItemType::Bytes(_) => format!("(len({key_name}), {key_name}[0] if {key_name} else 0)"),
+                _ => key_name.to_string(),
+            },
+            OutputLang::C => key_name.to_string(),
+        }
+    }
+
+    /// Mixes the byte of the key at a position into the positions hash, from the end of the
+    /// key when negative. Past the end of the key, the byte reads as 0
+    pub fn get_position_mix(&self, position: i32) -> String {
+        match self {
+            OutputLang::C => {
+                let byte = if position >= 0 {
+                    format!("(sz > {position} ? cdata[{position}] : 0)")
+                } else {
+                    format!("(sz >= {} ? cdata[sz - {}] : 0)", -position, -position)
+                };
+
+                format!("    h = (h ^ {byte}) * 0x01000193;\n")
+            }
+            OutputLang::Python => {
+                let byte = if position >= 0 {
+                    format!("(data[{position}] if size > {position} else 0)")
+                } else {
+                    format!("(data[{position}] if size >= {} else 0)", -position)
+                };
+
+                format!("    h = ((h ^ {byte}) * 0x01000193) & 0xFFFFFFFF\n")
+            }
+        }
+    }
+
+    /// Type of the values of tables of gperf structs, pointing to the entries of the keys
+    pub fn get_entry_type(&self, struct_name: &str) -> String {
+        match self {
+            OutputLang::C => format!("struct {struct_name}*"),
+            OutputLang::Python => "tuple".to_string(),
+        }
+    }
+
+    pub fn get_entry_return_type(&self, entry_type: &str) -> String {
+        match self {
+            OutputLang::C => format!("const {entry_type}"),
+            OutputLang::Python => format!("{entry_type} | None"),
+        }
+    }
+
+    /// Initializer of an entry, from the keyword and its fields
+    pub fn get_entry_literal(&self, initializer: &str) -> String {
+        match self {
+            OutputLang::C => format!("{{{initializer}}}"),
+            OutputLang::Python => format!("({initializer},)"),
+        }
+    }
+
+    pub fn get_entry_reference(&self, entries_name: &str, index: usize) -> String {
+        match self {
+            OutputLang::C => format!("&{entries_name}[{index}]"),
+            OutputLang::Python => format!("{entries_name}[{index}]"),
+        }
+    }
+
+    /// Access to the keyword of an entry, appended to the entry
+    pub fn get_entry_key_suffix(&self, slot_name: &str) -> String {
+        match self {
+            OutputLang::C => format!("->{slot_name}"),
+            OutputLang::Python => "[0]".to_string(),
+        }
+    }
+
+    /// Access to the size of the key of an entry, appended to the entry. Only C byte strings
+    /// are stored with their size
+    pub fn get_entry_size_suffix(&self, t: &ItemType) -> String {
+        match (self, t) {
+            (OutputLang::C, ItemType::Bytes(_)) => "->len".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Initializer of the key of an entry, followed by its size for C byte strings
+    pub fn get_key_initializer(&self, key: &ItemType) -> String {
+        match (self, key) {
+            (OutputLang::C, ItemType::Bytes(bytes)) => format!("{}, {}", self.get_key_literal(key), bytes.len()),
+            _ => self.get_key_literal(key),
+        }
+    }
+
+    /// Literal of a key in the tables
+    pub fn get_key_literal(&self, key: &ItemType) -> String {
+        match (self, key) {
+            (_, ItemType::Str(s)) => self.get_string_literal(s),
+            (_, ItemType::Bytes(bytes)) => self.get_bytes_literal(bytes),
+            // 64-bits C literals are suffixed, the smallest integer is written as an expression
+            (OutputLang::C, ItemType::I64(i64::MIN)) => format!("({}LL - 1)", i64::MIN + 1),
+            (OutputLang::C, ItemType::I64(i)) => format!("{i}LL"),
+            (OutputLang::C, ItemType::U64(u)) => format!("{u}ULL"),
+            _ => key.to_string(),
+        }
+    }
+
+    /// String literal escaping anything but printable ASCII, the generated files stay ASCII
+    /// whatever the keys. C strings hold the UTF-8 bytes of the string
+    pub fn get_string_literal(&self, s: &str) -> String {
+        match self {
+            OutputLang::C => self.get_bytes_literal(s.as_bytes()),
+            OutputLang::Python => {
+                let mut literal = String::from("\"");
+
+                for c in s.chars() {
+                    match c {
+                        '"' => literal += "\\\"",
+                        '\\' => literal += "\\\\",
+                        '\n' => literal += "\\n",
+                        '\r' => literal += "\\r",
+                        '\t' => literal += "\\t",
+                        ' '..='~' => literal.push(c),
+                        '\0'..='\u{ff}' => literal += &format!("\\x{:02x}", c as u32),
+                        '\u{100}'..='\u{ffff}' => literal += &format!("\\u{:04x}", c as u32),
+                        _ => literal += &format!("\\U{:08x}", c as u32),
+                    }
+                }
+
+                return literal + "\"";
+            }
+        }
+    }
+
+    /// Byte string literal escaping anything but printable ASCII. C escapes are octal, as they
+    /// stop after three digits where hexadecimal ones run into the next digits
+    pub fn get_bytes_literal(&self, bytes: &[u8]) -> String {
+        let mut literal = match self {
+            OutputLang::C => String::from("\""),
+            OutputLang::Python => String::from("b\""),
+        };
+
+        for &byte in bytes.iter() {
+            match (self, byte) {
+                (_, b'"') => literal += "\\\"",
+                (_, b'\\') => literal += "\\\\",
+                (_, b'\n') => literal += "\\n",
+                (_, b'\r') => literal += "\\r",
+                (_, b'\t') => literal += "\\t",
+                // Two question marks could start a trigraph
+                (OutputLang::C, b'?') => literal += "\\?",
+                (_, 0x20..=0x7e) => literal.push(byte as char),
+                (OutputLang::C, _) => literal += &format!("\\{byte:03o}"),
+                (OutputLang::Python, _) => literal += &format!("\\x{byte:02x}"),
+            }
+        }
+
+        return literal + "\"";
+    }
+
+    pub fn get_null_literal(&self) -> &str {
+        match self {
+            OutputLang::C => "NULL",
+            OutputLang::Python => "None",
+        }
+    }
+
+    pub fn get_imports_for_normalization(&self, normalization: &Normalization) -> Option<String> {
+        match self {
+            OutputLang::Python if normalization.form.is_some() => Some("import unicodedata\n".to_string()),
+            _ => None,
+        }
+    }
+
+    /// Statements normalizing the looked up key like the keys of the table. In C, they narrow
+    /// down start and size, and fold the case into the normalized buffer
+    pub fn get_normalization_steps(&self, normalization: &Normalization, t: &ItemType, fold_name: &str, miss: &str) -> String {
+        let is_bytes = matches!(t, ItemType::Bytes(_));
+        let literal = |s: &str| if is_bytes { self.get_bytes_literal(s.as_bytes()) } else { self.get_string_literal(s) };
+        let mut steps = String::new();
+
+        match self {
+            OutputLang::C => {
+                if normalization.trim {
+                    let is_space = |c: &str| format!("({c} == ' ' || ({c} >= '\\t' && {c} <= '\\r'))");
+
+                    steps += &format!(
+                        "\n    while(size > 0 && {})\n    {{\n        start++;\n        size--;\n    }}\n",
+                        is_space("start[0]")
+                    );
+                    steps += &format!("\n    while(size > 0 && {})\n    {{\n        size--;\n    }}\n", is_space("start[size - 1]"));
+                }
+
+                if normalization.case.is_some() {
+                    steps += &format!(
+                        "\n    size = {fold_name}(start, size, normalized, sizeof(normalized) - 1);\n\n    if(size >= sizeof(normalized))\n    {{\n        return {miss};\n    }}\n\n    start = normalized;\n"
+                    );
+                }
+
+                for (i, prefix) in normalization.prefixes.iter().enumerate() {
+                    let keyword = if i == 0 { "\n    if" } else { "    else if" };
+                    let size = prefix.len();
+
+                    steps += &format!(
+                        "{keyword}(size >= {size} && memcmp(start, {}, {size}) == 0)\n    {{\n        start += {size};\n        size -= {size};\n    }}\n",
+                        literal(prefix)
+                    );
+                }
+            }
+            OutputLang::Python => {
+                if normalization.trim {
+                    steps += &format!("    key = key.strip({})\n", literal(&normalize::WHITESPACE.iter().collect::<String>()));
+                }
+
+                if let Some(form) = normalization.form {
+                    steps += &format!("    key = unicodedata.normalize(\"{}\", key)\n", form.name());
+                }
+
+                // The bytes of multibyte UTF-8 characters are not ASCII letters
+                match (normalization.case, is_bytes) {
+                    (Some(Case::Lower), true) => steps += "    key = key.lower()\n",
+                    (Some(Case::Lower), false) => {
+                        steps += "    key = key.encode(\"utf-8\", \"surrogatepass\").lower().decode(\"utf-8\", \"surrogatepass\")\n"
+                    }
+                    (Some(Case::Casefold), _) => steps += "    key = key.casefold()\n",
+                    (None, _) => {}
+                }
+
+                for (i, prefix) in normalization.prefixes.iter().enumerate() {
+                    let keyword = if i == 0 { "if" } else { "elif" };
+                    let size = if is_bytes { prefix.len() } else { prefix.chars().count() };
+
+                    steps += &format!("    {keyword} key.startswith({}):\n        key = key[{size}:]\n", literal(prefix));
+                }
+            }
+        }
+
+        return steps;
+    }
+
+    pub fn get_imports_for_entries(&self) -> Option<String> {
+        match self {
+            OutputLang::C => Some("#include <stddef.h>\n".to_string()),
+            OutputLang::Python => None,
+        }
+    }
+
+    /// Type of the values of keys, C arrays point to their elements
+    pub fn get_value_type(&self, t: &ValueType) -> String {
+        match self {
+            OutputLang::C => match t {
+                ValueType::Bool => "int".to_string(),
+                ValueType::Int => "long long".to_string(),
+                ValueType::Float => "double".to_string(),
+                ValueType::Str => "const char*".to_string(),
+                ValueType::Array(Some(element_type)) => format!("{} const*", self.get_value_type(element_type)),
+                ValueType::Array(None) => "int const*".to_string(),
+            },
+            OutputLang::Python => match t {
+                ValueType::Bool => "bool".to_string(),
+                ValueType::Int => "int".to_string(),
+                ValueType::Float => "float".to_string(),
+                ValueType::Str => "str".to_string(),
+                ValueType::Array(_) => "tuple".to_string(),
+            },
+        }
+    }
+
+    /// Declaration of the struct of the entries of keys, with their values if any. C byte
+    /// strings and arrays are followed by their size. None when the entries are tuples
+    pub fn get_values_struct(&self, struct_name: &str, key_type: &ItemType, value_type: Option<&ValueType>) -> Option<String> {
+        match self {
+            OutputLang::C => {
+                let key_size = match key_type {
+                    ItemType::Bytes(_) => "    size_t len;\n",
+                    _ => "",
+                };
+
+                let value = match value_type {
+                    Some(value_type @ ValueType::Array(_)) => {
+                        format!("    {} value;\n    unsigned int value_size;\n", self.get_value_type(value_type))
+                    }
+                    Some(value_type) => format!("    {} value;\n", self.get_value_type(value_type)),
+                    None => String::new(),
+                };
+
+                Some(format!(
+                    "struct {struct_name}\n{{\n    {} key;\n{key_size}{value}}};\n",
+                    self.get_return_type(key_type)
+                ))
+            }
+            OutputLang::Python => None,
+        }
+    }
+
+    /// Literal of the value of a key, of the type of the values of all the keys
+    pub fn get_value_literal(&self, value: &Value, t: &ValueType) -> String {
+        if let (Value::Array(values), ValueType::Array(element_type)) = (value, t) {
+            let element_type = element_type.as_deref().unwrap_or(&ValueType::Int);
+            let elements: Vec<String> = values.iter().map(|value| self.get_value_literal(value, element_type)).collect();
+
+            match self {
+                OutputLang::C if elements.is_empty() => return "NULL, 0".to_string(),
+                OutputLang::C => {
+                    return format!(
+                        "({} const[]){{{}}}, {}",
+                        self.get_value_type(element_type),
+                        elements.join(", "),
+                        elements.len()
+                    );
+                }
+                OutputLang::Python if elements.len() == 1 => return format!("({},)", elements[0]),
+                OutputLang::Python => return format!("({})", elements.join(", ")),
+            }
+        }
+
+        match self {
+            OutputLang::C => match value {
+                Value::Bool(b) => self.get_bool_literal(*b).to_string(),
+                // The smallest integer is written as an expression, its negation overflows
+                Value::Int(i64::MIN) => format!("({}LL - 1)", i64::MIN + 1),
+                Value::Int(i) => format!("{i}LL"),
+                Value::Float(f) => format!("{f:?}"),
+                Value::Str(s) => self.get_string_literal(s),
+                Value::Array(_) => panic!("Arrays are of an array type"),
+            },
+            OutputLang::Python => match value {
+                Value::Bool(b) => self.get_bool_literal(*b).to_string(),
+                Value::Int(i) => i.to_string(),
+                Value::Float(f) => format!("{f:?}"),
+                Value::Str(s) => self.get_string_literal(s),
+                Value::Array(_) => panic!("Arrays are of an array type"),
+            },
+        }
+    }
+
+    /// Key argument of bisect when the values are not the keys
+    pub fn get_bisect_key(&self, key_suffix: &str) -> String {
+        match key_suffix {
+            "" => String::new(),
+            _ => format!(", key=lambda value: value{key_suffix}"),
+        }
+    }
+
+    pub fn get_ternary(&self, condition: &str, if_true: &str, if_false: &str) -> String {
+        match self {
+            OutputLang::C => format!("{condition} ? {if_true} : {if_false}"),
+            OutputLang::Python => format!("{if_true} if {condition} else {if_false}"),
+        }
+    }
+
+    /// Value returned by a lookup that misses, it never compares equal to the looked up key
+    pub fn get_miss_value(&self, t: &ItemType, key_name: &str) -> String {
+        match self {
+            OutputLang::C => match t {
+                ItemType::Str(_) | ItemType::Bytes(_) => "NULL".to_string(),
+                _ => format!("~{key_name}"),
+            },
+            OutputLang::Python => "None".to_string(),
+        }
+    }
+
     pub fn get_fo_hash_data(&self, name: &str) -> Option<FOHashData> {
         return FO_HASHES
             .functions
@@ -285,7 +765,75 @@ impl OutputLang {
             .cloned();
     }
 
-    pub fn get_get_data(&self) -> Option<GetData> {
-        return GETS.functions.get(&self.to_string()).cloned();
+    pub fn get_get_data(&self, strategy: &str) -> Option<GetData> {
+        return GETS
+            .functions
+            .get(strategy)
+            .and_then(|map| map.get(&self.to_string()))
+            .cloned();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn c_literals_escape_everything_but_printable_ascii() {
+        let cases = [
+            ("plain", "key", r#""key""#),
+            ("quotes", r#"say "hi""#, r#""say \"hi\"""#),
+            ("backslash", r"a\b", r#""a\\b""#),
+            ("trigraph", "??=", r#""\?\?=""#),
+            ("line breaks", "a\r\nb\t", r#""a\r\nb\t""#),
+            ("non-ASCII as octal", "é", r#""\303\251""#),
+            ("octal before a digit", "\u{1}2", r#""\0012""#),
+            ("nul", "\0", r#""\000""#),
+            ("braces", "{x}", r#""{x}""#),
+        ];
+
+        for (name, s, expected) in cases {
+            assert_eq!(OutputLang::C.get_string_literal(s), expected, "{name}");
+            assert_eq!(OutputLang::C.get_bytes_literal(s.as_bytes()), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn python_literals_escape_everything_but_printable_ascii() {
+        let cases = [
+            ("plain", "key", r#""key""#),
+            ("quotes", r#"say "hi""#, r#""say \"hi\"""#),
+            ("backslash", r"a\b", r#""a\\b""#),
+            ("question marks", "??=", r#""??=""#),
+            ("line breaks", "a\r\nb\t", r#""a\r\nb\t""#),
+            ("latin-1", "é\0", r#""\xe9\x00""#),
+            ("bmp", "€", r#""\u20ac""#),
+            ("astral", "😀", r#""\U0001f600""#),
+            ("braces", "{x}", r#""{x}""#),
+        ];
+
+        for (name, s, expected) in cases {
+            assert_eq!(OutputLang::Python.get_string_literal(s), expected, "{name}");
+        }
+
+        assert_eq!(OutputLang::Python.get_bytes_literal("é\"{\\".as_bytes()), r#"b"\xc3\xa9\"{\\""#);
+    }
+
+    #[test]
+    fn c_64_bits_integer_keys_are_suffixed() {
+        let cases = [
+            (ItemType::I32(i32::MIN), "-2147483648"),
+            (ItemType::U32(u32::MAX), "4294967295"),
+            (ItemType::I64(-5), "-5LL"),
+            (ItemType::I64(i64::MIN), "(-9223372036854775807LL - 1)"),
+            (ItemType::U64(u64::MAX), "18446744073709551615ULL"),
+        ];
+
+        for (key, expected) in cases {
+            assert_eq!(OutputLang::C.get_key_literal(&key), expected);
+        }
+
+        assert_eq!(OutputLang::Python.get_key_literal(&ItemType::I64(i64::MIN)), "-9223372036854775808");
+        assert_eq!(OutputLang::Python.get_key_literal(&ItemType::U64(u64::MAX)), "18446744073709551615");
     }
 }
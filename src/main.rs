@@ -1,55 +1,379 @@
-use clap::Parser;
-use phash::PHash;
+#![allow(clippy::needless_return)]
+
+use clap::{Parser, Subcommand};
+use config::{Config, Files};
+use generate::Strategy;
+use input::{InputFile, InputOptions};
+use normalize::Normalization;
+use phash::{Algorithm, BuildParams, KeyType, PHash};
 use std::path::PathBuf;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
+pub mod bbhash;
+pub mod bdz;
+pub mod bits;
+pub mod chm;
+pub mod config;
+pub mod dense;
+pub mod fks;
 pub mod generate;
+pub mod gperf;
 pub mod hash;
+pub mod input;
 pub mod lang;
+pub mod monotone;
+pub mod normalize;
 pub mod phash;
+pub mod recsplit;
+pub mod retrieval;
+pub mod tune;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Project config file, missing options are read from it
+    #[arg(long, global = true, default_value = config::DEFAULT_CONFIG_FILE)]
+    config: PathBuf,
+
+    /// Keys, one per line or separated by commas (tabs for .tsv files) with quoted fields holding
+    /// delimiters, JSON (.json) or JSON Lines (.jsonl) keys and records, or the keywords of a
+    /// gperf file (.gperf). Several files and glob patterns are merged into one keyset, - reads
+    /// the standard input
+    #[arg(short, long, global = true, num_args = 1..)]
+    file: Vec<String>,
+
+    /// Type of the keys: string, bytes for keys that may not be UTF-8, or i32, i64, u32 and u64
+    /// for integers written in decimal or hexadecimal (0x). Dense ranges of integer keys are
+    /// served by direct-index tables [default: string]
     #[arg(short, long)]
-    file: PathBuf,
+    key_type: Option<String>,
+
+    /// Format of the input file: csv, json for an array of keys or records or an object mapping
+    /// keys to values, or jsonl for a key or record per line [default: from the extension of
+    /// the file, csv otherwise]
+    #[arg(long)]
+    input_format: Option<String>,
+
+    /// Encoding of the keys of the input file: raw, escaped for \\xNN escapes, hex or base64
+    /// [default: raw]
+    #[arg(long)]
+    key_encoding: Option<String>,
+
+    /// Comma separated transforms applied to the keys, and to the looked up key before hashing
+    /// it: trim, nfc or nfkc, lower (ASCII) or casefold (Unicode). Keys equal once normalized
+    /// are duplicates
+    #[arg(long)]
+    normalize: Option<String>,
+
+    /// Prefix stripped from the keys once normalized, can be repeated, the first matching one
+    /// is stripped
+    #[arg(long)]
+    strip_prefix: Vec<String>,
 
-    #[arg(short, long, default_value = "string")]
-    key_type: String,
+    /// Separator of the fields of the input file, a single character or tab [default: comma,
+    /// tab for .tsv files]
+    #[arg(long)]
+    delimiter: Option<String>,
 
-    #[arg(short, long, default_value = "pho_output.c")]
-    output: PathBuf,
+    /// Character taking the next one literally in the fields of the input file, like \\
+    #[arg(long)]
+    escape: Option<char>,
 
+    /// The first line of the input file names its columns
+    #[arg(long, default_value_t = false)]
+    header: bool,
+
+    /// Column of the keys, numbered from 1 or named in the header. Every field is a key if
+    /// unset. Field of the keys of JSON records [default: key, or the first one of arrays]
+    #[arg(long)]
+    key_column: Option<String>,
+
+    /// Column of the values of retrieval tables, numbered from 1 or named in the header
+    /// [default: the column after the keys]. Field of the values of JSON records [default:
+    /// value, or the one after the key of arrays]
+    #[arg(long)]
+    value_column: Option<String>,
+
+    /// [default: pho_output.c]
     #[arg(short, long)]
-    name: String,
+    output: Option<PathBuf>,
+
+    #[arg(short, long)]
+    name: Option<String>,
+
+    /// [default: pho]
+    #[arg(long)]
+    namespace: Option<String>,
+
+    /// Construction algorithm: chd, pthash for smaller pilots tables, recsplit for the smallest
+    /// tables, bbhash for huge keysets, bdz for predictable build times, chm to keep the keys in
+    /// input order, monotone to keep them in lexicographic order, retrieval to map key,value
+    /// lines to their values without storing the keys, kperfect to scan groups of slots for
+    /// faster builds and smaller seeds tables, or fks for the simplest lookup [default: chd]
+    #[arg(long)]
+    algorithm: Option<String>,
 
-    #[arg(long, default_value = "pho")]
-    namespace: String,
+    /// fnv1a, xxhash32, murmur3, xxhash64, or positions to only hash the length of the keys
+    /// and their bytes at the positions telling them apart [default: murmur3]
+    #[arg(long)]
+    first_order_hash: Option<String>,
 
-    #[arg(long, default_value = "murmur3")]
-    first_order_hash: String,
+    /// [default: xorshift]
+    #[arg(long)]
+    second_order_hash: Option<String>,
 
-    #[arg(long, default_value = "xorshift")]
-    second_order_hash: String,
+    /// Average number of keys per bucket (lambda), trades construction time against seeds table size.
+    /// Number of keys per bucket of monotone tables [default: 4, 100 for recsplit, 16 for monotone, 8 for kperfect, 1 for fks]
+    #[arg(long)]
+    bucket_size: Option<f64>,
+
+    /// Fraction of the slots filled during the seed search, pthash tables stay minimal by
+    /// remapping the extra slots [default: 1, 0.97 for kperfect]
+    #[arg(long)]
+    load_factor: Option<f64>,
+
+    /// Maximum number of keys of the leaves of recsplit trees [default: 8]
+    #[arg(long)]
+    leaf_size: Option<usize>,
+
+    /// Number of parts the nodes of recsplit trees are split into [default: 4]
+    #[arg(long)]
+    fanout: Option<usize>,
+
+    /// Bits per key of each level of bbhash tables [default: 2]
+    #[arg(long)]
+    gamma: Option<f64>,
+
+    /// Maximum number of keys sharing a group of slots of kperfect tables [default: as many
+    /// keys as fit in a 64 bytes cache line]
+    #[arg(long)]
+    group_size: Option<usize>,
+
+    /// Stores a fingerprint of 8, 16 or 32 bits per key instead of the key, the generated
+    /// `_contains` lookup then accepts a non-member with a probability of 2^-bits, times the
+    /// group size for kperfect tables
+    #[arg(long)]
+    fingerprint_bits: Option<u32>,
+
+    /// Lookup code: linear, switch, bsearch or phash, or auto to pick the cheapest one by
+    /// estimated lookup cost [default: phash with --algorithm, auto otherwise]
+    #[arg(long)]
+    strategy: Option<String>,
+
+    /// Number of seeds tried for a bucket before falling back to other parameters
+    /// [default: 1000000]
+    #[arg(long)]
+    max_attempts_per_bucket: Option<u64>,
+
+    /// Time budget in seconds of the search for a minimal table, after which pho falls back
+    /// to a non-minimal table
+    #[arg(long)]
+    timeout: Option<f64>,
+
+    /// Error out instead of retrying with other parameters when a limit is hit
+    #[arg(long, default_value_t = false)]
+    no_fallback: bool,
+
+    /// Number of threads used for construction [default: number of cores]
+    #[arg(short, long, global = true)]
+    jobs: Option<usize>,
+
+    /// Seed of the seed search, makes the generated table reproducible
+    #[arg(long, global = true)]
+    seed: Option<u64>,
 
     #[arg(long, short, default_value_t = false)]
     test: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Tries every pair of hashes and average bucket size on the keyset, and writes the best
+    /// parameters to the project config
+    Tune,
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
-    if !args.file.exists() {
-        return Err(format!("Cannot find file {}", args.file.display()).into());
+    let mut config = Config::load(&args.config)?;
+
+    let patterns = match args.file.is_empty() {
+        true => config.file.clone().map(Files::into_vec).unwrap_or_default(),
+        false => args.file.clone(),
+    };
+
+    if patterns.is_empty() {
+        return Err("Missing input file, use --file or set \"file\" in the project config".into());
+    }
+
+    let files = input::expand_files(&patterns)?
+        .iter()
+        .map(|path| InputFile::read(path))
+        .collect::<Result<Vec<InputFile>, String>>()?;
+
+    let defaults = BuildParams::default();
+
+    let key_type = match args.key_type.or(config.key_type.clone()) {
+        Some(key_type) => key_type.parse()?,
+        None => KeyType::default(),
+    };
+
+    // Keywords of gperf files with %ignore-case are lowercased, like --normalize lower
+    let ignore_case = files.iter().filter_map(|file| file.gperf.as_ref()).any(|gperf| gperf.ignore_case);
+
+    let input = InputOptions {
+        format: args
+            .input_format
+            .or(config.input_format.clone())
+            .map(|format| format.parse())
+            .transpose()?,
+        key_encoding: args
+            .key_encoding
+            .or(config.key_encoding.clone())
+            .map(|encoding| encoding.parse())
+            .transpose()?
+            .unwrap_or_default(),
+        normalization: args
+            .normalize
+            .or(config.normalize.clone())
+            .map(|normalize| normalize.parse::<Normalization>())
+            .transpose()?
+            .unwrap_or_default()
+            .with_ignore_case(ignore_case)
+            .with_prefixes(match args.strip_prefix.is_empty() {
+                true => config.strip_prefix.as_deref().unwrap_or_default(),
+                false => &args.strip_prefix,
+            })?,
+        delimiter: args
+            .delimiter
+            .or(config.delimiter.clone())
+            .map(|delimiter| input::parse_delimiter(&delimiter))
+            .transpose()?,
+        escape: args.escape.or(config.escape),
+        header: args.header || config.header.unwrap_or(false),
+        key_column: args.key_column.or(config.key_column.clone()).map(|column| column.parse()).transpose()?,
+        value_column: args.value_column.or(config.value_column.clone()).map(|column| column.parse()).transpose()?,
+    };
+
+    let jobs = args.jobs.or(config.jobs).unwrap_or(defaults.jobs);
+    let seed = args.seed.or(config.seed);
+
+    if let Some(Command::Tune) = args.command {
+        let keys = PHash::read_all_keys(&files, key_type, &input, true)?;
+        let names: Vec<String> = files.iter().map(|file| file.name()).collect();
+
+        println!("Tuning perfect hash parameters for file: \"{}\"", names.join("\", \""));
+
+        let candidates = tune::tune(&keys, jobs, seed)?;
+
+        println!();
+        println!("{:<4} {:<10} {:<10} {:>6} {:>10} {:>12} {:>8}", "rank", "fo hash", "so hash", "lambda", "time (ms)", "size (bytes)", "cost");
+
+        for (i, candidate) in candidates.iter().take(10).enumerate() {
+            println!(
+                "{:<4} {:<10} {:<10} {:>6.1} {:>10.3} {:>12} {:>8.1}",
+                i + 1,
+                candidate.first_order_hash,
+                candidate.second_order_hash,
+                candidate.bucket_size,
+                candidate.build_time.as_secs_f64() * 1000.0,
+                candidate.table_size,
+                candidate.lookup_cost
+            );
+        }
+
+        let best = &candidates[0];
+
+        config.first_order_hash = Some(best.first_order_hash.clone());
+        config.second_order_hash = Some(best.second_order_hash.clone());
+        config.bucket_size = Some(best.bucket_size);
+        config.save(&args.config)?;
+
+        println!();
+        println!("Wrote best parameters to \"{}\"", args.config.display());
+
+        return Ok(());
+    }
+
+    let name = args
+        .name
+        .or(config.name)
+        .ok_or("Missing table name, use --name or set \"name\" in the project config")?;
+
+    let requested_algorithm = args.algorithm.or(config.algorithm);
+
+    let algorithm = match requested_algorithm.as_deref() {
+        Some(algorithm) => algorithm.parse()?,
+        None => defaults.algorithm,
+    };
+
+    let fingerprint_bits = args.fingerprint_bits.or(config.fingerprint_bits);
+
+    let strategy = Strategy::requested(args.strategy.or(config.strategy).as_deref(), requested_algorithm.is_some())?;
+
+    let params = BuildParams {
+        algorithm,
+        first_order_hash: args
+            .first_order_hash
+            .or(config.first_order_hash)
+            .unwrap_or(defaults.first_order_hash),
+        second_order_hash: args
+            .second_order_hash
+            .or(config.second_order_hash)
+            .unwrap_or(defaults.second_order_hash),
+        bucket_size: args
+            .bucket_size
+            .or(config.bucket_size)
+            .unwrap_or(algorithm.default_bucket_size()),
+        load_factor: args
+            .load_factor
+            .or(config.load_factor)
+            .unwrap_or(algorithm.default_load_factor()),
+        leaf_size: args.leaf_size.or(config.leaf_size).unwrap_or(defaults.leaf_size),
+        fanout: args.fanout.or(config.fanout).unwrap_or(defaults.fanout),
+        gamma: args.gamma.or(config.gamma).unwrap_or(defaults.gamma),
+        group_size: args.group_size.or(config.group_size),
+        max_attempts_per_bucket: args
+            .max_attempts_per_bucket
+            .or(config.max_attempts_per_bucket)
+            .or(defaults.max_attempts_per_bucket),
+        timeout: args
+            .timeout
+            .or(config.timeout)
+            .map(Duration::try_from_secs_f64)
+            .transpose()
+            .map_err(|e| format!("Invalid timeout: {}", e))?,
+        fallbacks: !args.no_fallback,
+        jobs,
+        seed,
+        key_type,
+        input,
+        // Direct-index tables store the keys, there is no fingerprint to compare
+        dense_ranges: fingerprint_bits.is_none(),
+        ..defaults
+    };
+
+    if let Some(bits) = fingerprint_bits {
+        if ![8, 16, 32].contains(&bits) {
+            return Err(format!("Invalid fingerprint size {bits}, it must be 8, 16 or 32 bits").into());
+        }
+
+        if algorithm == Algorithm::Retrieval {
+            return Err("Retrieval tables do not store keys, they cannot store fingerprints either".into());
+        }
+
+        if params.first_order_hash == "positions" {
+            return Err("The positions hash only reads a few bytes of the keys, their fingerprints would not tell non-members apart".into());
+        }
     }
 
     let start = Instant::now();
 
-    let phash = PHash::from_file(
-        &args.file,
-        &args.first_order_hash,
-        &args.second_order_hash,
-    )?;
+    let phash = PHash::from_files(&files, &params)?;
 
     let elapsed = start.elapsed();
     let ms = elapsed.as_millis();
@@ -57,10 +381,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Perfect Hash found in {} ms", ms);
 
     return generate::gen_code(
-        args.output,
+        args.output
+            .or(config.output)
+            .unwrap_or(PathBuf::from("pho_output.c")),
         &phash,
-        args.name.as_str(),
-        args.namespace.as_str(),
+        name.as_str(),
+        args.namespace
+            .or(config.namespace)
+            .unwrap_or("pho".to_string())
+            .as_str(),
+        fingerprint_bits,
+        strategy,
         args.test,
     );
 }
@@ -0,0 +1,183 @@
+use super::bdz;
+use super::hash::{splitmix64, FOHash};
+use super::retrieval::StaticFunction;
+
+// https://vigna.di.unimi.it/ftp/papers/MonotoneMinimalPerfectHashing.pdf
+
+// Number of keys per bucket, the rank of a key within its bucket takes log2 of it
+pub const DEFAULT_BUCKET_SIZE: f64 = 16.0;
+
+// 2^64 divided by the golden ratio
+const PREFIX_MULTIPLIER: u64 = 0x9e3779b97f4a7c15;
+
+/// Monotone minimal perfect hash: the index of a key is its rank in lexicographic order.
+/// Sorted keys are cut in buckets, each identified by the longest prefix in bits of its keys,
+/// a first function gives the length of that prefix, whether it is a whole key, and the rank
+/// of the key in its bucket, a second one gives the rank of the first key of a bucket from its
+/// prefix
+#[derive(Debug, Clone, Default)]
+pub struct Monotone {
+    /// Length of the prefix of the bucket of a key and rank of the key in its bucket, by key
+    pub keys: StaticFunction,
+    /// Rank of the first key of a bucket, by prefix
+    pub buckets: StaticFunction,
+    /// Seed of the first-order hash of the prefixes, so that they do not collide
+    pub prefix_seed: u64,
+    /// Number of low bits of the values of the first function holding the rank in the bucket,
+    /// followed by a bit set when the prefix is the whole first key of the bucket
+    pub rank_bits: usize,
+    pub num_buckets: usize,
+    pub m: usize,
+}
+
+impl Monotone {
+    /// Builds the function of keys sorted in lexicographic order, along with their fingerprints.
+    /// Returns the number of attempts if no attempt gave peelable hypergraphs or distinct prefixes
+    pub fn new(
+        keys: &[&[u8]],
+        fingerprints: &[u64],
+        fo_hash: &FOHash,
+        bucket_size: usize,
+        stage_seed: u64,
+    ) -> Result<Monotone, u64> {
+        let m = keys.len();
+        let buckets = Monotone::buckets(keys, bucket_size.max(1));
+        let rank_bits = (usize::BITS - (bucket_size.max(1) - 1).leading_zeros()) as usize;
+
+        let mut key_values = vec![0u64; m];
+
+        for &(start, end, length, whole) in buckets.iter() {
+            for (rank, value) in key_values[start..end].iter_mut().enumerate() {
+                *value = (((length as u64) << 1 | whole as u64) << rank_bits) | rank as u64;
+            }
+        }
+
+        let keys_function = StaticFunction::new(fingerprints, &key_values, fo_hash, stage_seed)?;
+
+        let starts: Vec<u64> = buckets.iter().map(|&(start, _, _, _)| start as u64).collect();
+
+        for attempt in 0..bdz::MAX_ATTEMPTS {
+            let prefix_seed = splitmix64(!stage_seed ^ attempt);
+            let prefix_seed = if fo_hash.is_64bits() { prefix_seed } else { prefix_seed as u32 as u64 };
+
+            let prefixes: Vec<u64> = buckets
+                .iter()
+                .map(|&(start, _, length, whole)| Monotone::prefix_hash(fo_hash, keys[start], length, whole, prefix_seed))
+                .collect();
+
+            let mut sorted = prefixes.clone();
+            sorted.sort_unstable();
+
+            if sorted.windows(2).any(|w| w[0] == w[1]) {
+                continue;
+            }
+
+            let buckets_function = StaticFunction::new(&prefixes, &starts, fo_hash, !stage_seed)?;
+
+            return Ok(Monotone {
+                keys: keys_function,
+                buckets: buckets_function,
+                prefix_seed,
+                rank_bits,
+                num_buckets: buckets.len(),
+                m,
+            });
+        }
+
+        return Err(bdz::MAX_ATTEMPTS);
+    }
+
+    // Cuts the sorted keys in buckets of bucket_size keys. Returns the range of keys of each
+    // bucket, the length in bits of the longest prefix its keys share, and whether that prefix
+    // is the whole first key. Those prefixes are distinct: the first and last keys of a bucket
+    // differ right after it, so the keys of the next buckets do not share it, and a whole first
+    // key is only the prefix of its own bucket
+    fn buckets(keys: &[&[u8]], bucket_size: usize) -> Vec<(usize, usize, usize, bool)> {
+        let mut buckets = Vec::new();
+
+        for start in (0..keys.len()).step_by(bucket_size) {
+            let end = (start + bucket_size).min(keys.len());
+            let (first, last) = (keys[start], keys[end - 1]);
+
+            // The last key is greater, the first key ends first when it is a prefix of it, or
+            // when the bucket holds a single key
+            let (length, whole) = match first.iter().zip(last.iter()).position(|(a, b)| a != b) {
+                Some(i) => (8 * i + (first[i] ^ last[i]).leading_zeros() as usize, false),
+                None => (8 * first.len(), true),
+            };
+
+            buckets.push((start, end, length, whole));
+        }
+
+        return buckets;
+    }
+
+    // Fingerprint of the first bits of a key: the whole bytes are hashed, the remaining bits and
+    // the whole key bit are spread over the 64 bits of the hash by an odd multiplier, so that
+    // short prefixes do not only differ by a few bits
+    fn prefix_hash(fo_hash: &FOHash, key: &[u8], bits: usize, whole: bool, seed: u64) -> u64 {
+        let (bytes, rest) = (bits / 8, bits % 8);
+        let partial = if rest == 0 { 0 } else { key.get(bytes).copied().unwrap_or(0) & (0xff << (8 - rest)) };
+        let tail = (whole as u64) << 11 | (rest as u64) << 8 | partial as u64;
+
+        return fo_hash.hash_with_seed(&key[..bytes], seed).as_u64() ^ tail.wrapping_mul(PREFIX_MULTIPLIER);
+    }
+
+    /// Rank of a key, None for some of the other keys
+    pub fn index(&self, fo_hash: &FOHash, key: &[u8], fingerprint: u64) -> Option<usize> {
+        let value = self.keys.get(fo_hash, fingerprint);
+        let length = (value >> (self.rank_bits + 1)) as usize;
+        let whole = (value >> self.rank_bits) & 1 == 1;
+
+        if length / 8 > key.len() {
+            return None;
+        }
+
+        let prefix = Monotone::prefix_hash(fo_hash, key, length, whole, self.prefix_seed);
+        let rank = (self.buckets.get(fo_hash, prefix) + (value & ((1 << self.rank_bits) - 1))) as usize;
+
+        if rank >= self.m {
+            return None;
+        }
+
+        return Some(rank);
+    }
+
+    /// Size of both functions in bits
+    pub fn size_bits(&self) -> usize {
+        return self.keys.size_bits() + self.buckets.size_bits();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::XXHash64;
+
+    fn ranks(keys: &[&[u8]], bucket_size: usize) -> Vec<Option<usize>> {
+        let fo_hash = FOHash::XXHash64(XXHash64::default());
+        let fingerprints: Vec<u64> = keys.iter().map(|key| fo_hash.hash(&key.to_vec()).as_u64()).collect();
+        let monotone = Monotone::new(keys, &fingerprints, &fo_hash, bucket_size, 1).unwrap();
+
+        return keys.iter().zip(fingerprints.iter()).map(|(key, &fingerprint)| monotone.index(&fo_hash, key, fingerprint)).collect();
+    }
+
+    #[test]
+    fn keys_are_ranked_in_lexicographic_order() {
+        let keys: [&[u8]; 8] = [b"alpha", b"bravo", b"charlie", b"delta", b"echo", b"foxtrot", b"golf", b"hotel"];
+
+        for bucket_size in [1, 2, 3, 8, 16] {
+            assert_eq!(ranks(&keys, bucket_size), (0..keys.len()).map(Some).collect::<Vec<_>>(), "bucket size {bucket_size}");
+        }
+    }
+
+    #[test]
+    fn prefixes_of_keys_are_ranked_before_them() {
+        // The first key of a bucket is a prefix of the last one, up to null bytes
+        let keys: [&[u8]; 9] = [b"", b"\0", b"\0\0", b"a", b"a\0", b"a\0\x01", b"ab", b"ab\0", b"ab\x80"];
+
+        for bucket_size in [1, 2, 3, 4, 16] {
+            assert_eq!(ranks(&keys, bucket_size), (0..keys.len()).map(Some).collect::<Vec<_>>(), "bucket size {bucket_size}");
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use super::phash::ItemType;
+use caseless::Caseless;
+use std::collections::HashSet;
+use std::str::FromStr;
+use unicode_normalization::UnicodeNormalization;
+
+// Whitespace trimmed from the keys, the same in the generated lookups
+pub const WHITESPACE: &[char] = &[' ', '\t', '\n', '\r', '\x0b', '\x0c'];
+
+/// Unicode normalization form of the keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Form {
+    Nfc,
+    Nfkc,
+}
+
+impl Form {
+    pub fn name(&self) -> &str {
+        match self {
+            Form::Nfc => "NFC",
+            Form::Nfkc => "NFKC",
+        }
+    }
+}
+
+/// Case of the keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    /// ASCII letters only, the other characters are left as they are
+    Lower,
+    /// Full Unicode case folding
+    Casefold,
+}
+
+impl Case {
+    pub fn name(&self) -> &str {
+        match self {
+            Case::Lower => "lower",
+            Case::Casefold => "casefold",
+        }
+    }
+}
+
+/// Transforms applied to the keys when building the table, and to the looked up key before
+/// hashing it. They are applied in a fixed order: trimming, Unicode normalization, case and
+/// prefix stripping
+#[derive(Debug, Clone, Default)]
+pub struct Normalization {
+    pub trim: bool,
+    pub form: Option<Form>,
+    pub case: Option<Case>,
+    /// Prefixes stripped from the keys, the first matching one only. They are normalized like
+    /// the keys
+    pub prefixes: Vec<String>,
+}
+
+impl FromStr for Normalization {
+    type Err = String;
+
+    /// Comma separated transforms: lower, casefold, trim, nfc, nfkc
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut normalization = Normalization::default();
+
+        for transform in s.split(',').map(|transform| transform.trim()).filter(|transform| !transform.is_empty()) {
+            let (form, case) = match transform {
+                "trim" => {
+                    normalization.trim = true;
+                    (None, None)
+                }
+                "nfc" => (Some(Form::Nfc), None),
+                "nfkc" => (Some(Form::Nfkc), None),
+                "lower" => (None, Some(Case::Lower)),
+                "casefold" => (None, Some(Case::Casefold)),
+                _ => {
+                    return Err(format!(
+                        "Cannot find a corresponding key transform \"{transform}\". Expected: lower, casefold, trim, nfc, nfkc"
+                    ));
+                }
+            };
+
+            if let Some(form) = form {
+                if normalization.form.is_some_and(|other| other != form) {
+                    return Err("nfc and nfkc cannot be combined".to_string());
+                }
+
+                normalization.form = Some(form);
+            }
+
+            if let Some(case) = case {
+                if normalization.case.is_some_and(|other| other != case) {
+                    return Err("lower and casefold cannot be combined".to_string());
+                }
+
+                normalization.case = Some(case);
+            }
+        }
+
+        return Ok(normalization);
+    }
+}
+
+impl Normalization {
+    /// Lowercases the keys when they are compared case-insensitively, unless they are already
+    /// casefolded
+    pub fn with_ignore_case(mut self, ignore_case: bool) -> Normalization {
+        if ignore_case && self.case.is_none() {
+            self.case = Some(Case::Lower);
+        }
+
+        return self;
+    }
+
+    /// Adds the prefixes stripped from the keys
+    pub fn with_prefixes(mut self, prefixes: &[String]) -> Result<Normalization, String> {
+        for prefix in prefixes.iter() {
+            if prefix.is_empty() {
+                return Err("The prefixes stripped from the keys cannot be empty".to_string());
+            }
+
+            let prefix = self.fold(&self.normalize_form(prefix));
+            self.prefixes.push(prefix);
+        }
+
+        return Ok(self);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return !self.trim && self.form.is_none() && self.case.is_none() && self.prefixes.is_empty();
+    }
+
+    /// Transforms of the pipeline, in order, for messages
+    pub fn describe(&self) -> String {
+        let mut transforms = Vec::new();
+
+        if self.trim {
+            transforms.push("trim".to_string());
+        }
+
+        if let Some(form) = self.form {
+            transforms.push(form.name().to_string());
+        }
+
+        if let Some(case) = self.case {
+            transforms.push(case.name().to_string());
+        }
+
+        if !self.prefixes.is_empty() {
+            let prefixes: Vec<String> = self.prefixes.iter().map(|prefix| format!("{prefix:?}")).collect();
+            transforms.push(format!("strip prefix {}", prefixes.join(" or ")));
+        }
+
+        return transforms.join(", ");
+    }
+
+    /// Normalized key. Byte strings are only trimmed, lowercased and stripped of their prefix
+    pub fn apply(&self, key: ItemType) -> Result<ItemType, String> {
+        if self.is_empty() {
+            return Ok(key);
+        }
+
+        match key {
+            ItemType::Str(s) => return Ok(ItemType::Str(self.apply_str(&s))),
+            ItemType::Bytes(bytes) => {
+                if let Some(form) = self.form {
+                    return Err(format!("{} normalization only applies to string keys", form.name()));
+                }
+
+                if self.case == Some(Case::Casefold) {
+                    return Err("casefold only applies to string keys, use lower for byte strings".to_string());
+                }
+
+                return Ok(ItemType::Bytes(self.apply_bytes(&bytes)));
+            }
+            ItemType::I32(_) | ItemType::I64(_) | ItemType::U32(_) | ItemType::U64(_) => {
+                return Err("Key normalization only applies to string and byte string keys".to_string());
+            }
+        }
+    }
+
+    fn apply_str(&self, key: &str) -> String {
+        let key = if self.trim { key.trim_matches(WHITESPACE) } else { key };
+        let key = self.fold(&self.normalize_form(key));
+
+        return match self.prefixes.iter().find_map(|prefix| key.strip_prefix(prefix.as_str())) {
+            Some(stripped) => stripped.to_string(),
+            None => key,
+        };
+    }
+
+    fn apply_bytes(&self, key: &[u8]) -> Vec<u8> {
+        let mut key = key;
+
+        if self.trim {
+            while let [first, rest @ ..] = key
+                && WHITESPACE.contains(&(*first as char))
+            {
+                key = rest;
+            }
+
+            while let [rest @ .., last] = key
+                && WHITESPACE.contains(&(*last as char))
+            {
+                key = rest;
+            }
+        }
+
+        let key = match self.case {
+            Some(_) => key.to_ascii_lowercase(),
+            None => key.to_vec(),
+        };
+
+        return match self.prefixes.iter().find_map(|prefix| key.strip_prefix(prefix.as_bytes())) {
+            Some(stripped) => stripped.to_vec(),
+            None => key,
+        };
+    }
+
+    fn normalize_form(&self, key: &str) -> String {
+        match self.form {
+            Some(Form::Nfc) => return key.nfc().collect(),
+            Some(Form::Nfkc) => return key.nfkc().collect(),
+            None => return key.to_string(),
+        }
+    }
+
+    fn fold(&self, key: &str) -> String {
+        match self.case {
+            Some(Case::Lower) => return key.to_ascii_lowercase(),
+            Some(Case::Casefold) => return key.chars().default_case_fold().collect(),
+            None => return key.to_string(),
+        }
+    }
+
+    /// Non-ASCII characters changed by case folding, with their folding, sorted. Only the
+    /// characters folding to characters of the keys or prefixes are kept: the others fold to a
+    /// string that is not a key, and are not one either, leaving them as they are misses as well
+    pub fn folds<'a>(&self, keys: impl Iterator<Item = &'a ItemType>) -> Vec<(u32, String)> {
+        let mut alphabet: HashSet<char> = HashSet::new();
+
+        for key in keys {
+            if let ItemType::Str(s) = key {
+                alphabet.extend(s.chars());
+            }
+        }
+
+        for prefix in self.prefixes.iter() {
+            alphabet.extend(prefix.chars());
+        }
+
+        return (0x80..=char::MAX as u32)
+            .filter_map(char::from_u32)
+            .filter_map(|c| {
+                let folded: String = std::iter::once(c).default_case_fold().collect();
+                let changed = folded.chars().ne(std::iter::once(c));
+
+                (changed && folded.chars().all(|f| alphabet.contains(&f))).then_some((c as u32, folded))
+            })
+            .collect();
+    }
+}
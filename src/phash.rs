@@ -1,13 +1,28 @@
-use super::hash::{FOHash, Hashable, SOHash, HashKey, MXF64};
-use regex::Regex;
-use std::{path::PathBuf, str::FromStr};
-use std::cmp::max;
+use super::hash::{splitmix64, FOHash, Hashable, HashKey, HashSeed, SOHash, MXF64, XXHash64};
+use super::bbhash::{self, BBHash};
+use super::bdz::Bdz;
+use super::chm::Chm;
+use super::dense::DenseRanges;
+use super::fks::Fks;
+use super::gperf::Gperf;
+use super::input::{self, Field, InputFile, InputFormat, InputOptions, JsonRecord, Records, Value, ValueType, Values};
+use super::monotone::{self, Monotone};
+use super::normalize::Normalization;
+use super::bits::BitVec;
+use super::recsplit::{self, RecSplit, Splits, Tree, TreeSearch};
+use super::retrieval::StaticFunction;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::cmp::{max, min};
+use std::fmt::Display;
+use std::time::{Duration, Instant};
 
 // https://cmph.sourceforge.net/papers/esa09.pdf
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ItemType {
     Str(String),
+    Bytes(Vec<u8>),
     I64(i64),
     I32(i32),
     U64(u64),
@@ -20,14 +35,15 @@ impl Default for ItemType {
     }
 }
 
-impl ToString for ItemType {
-    fn to_string(&self) -> String {
+impl Display for ItemType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ItemType::Str(s) => format!("\"{}\"", s),
-            ItemType::I64(i64) => i64.to_string(),
-            ItemType::I32(i32) => i32.to_string(),
-            ItemType::U64(u64) => u64.to_string(),
-            ItemType::U32(u32) => u32.to_string(),
+            ItemType::Str(s) => write!(f, "\"{}\"", s.escape_debug()),
+            ItemType::Bytes(bytes) => write!(f, "b\"{}\"", bytes.escape_ascii()),
+            ItemType::I64(i64) => write!(f, "{}", i64),
+            ItemType::I32(i32) => write!(f, "{}", i32),
+            ItemType::U64(u64) => write!(f, "{}", u64),
+            ItemType::U32(u32) => write!(f, "{}", u32),
         }
     }
 }
@@ -36,12 +52,126 @@ impl ItemType {
     pub fn hashable(&self) -> Hashable {
         match self {
             ItemType::Str(s) => s.as_bytes().to_vec(),
+            ItemType::Bytes(bytes) => bytes.clone(),
             ItemType::I64(i64) => i64.to_le_bytes().to_vec(),
             ItemType::I32(i32) => i32.to_le_bytes().to_vec(),
             ItemType::U64(u64) => u64.to_le_bytes().to_vec(),
             ItemType::U32(u32) => u32.to_le_bytes().to_vec(),
         }
     }
+
+    /// Value of an integer key, None for strings
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            ItemType::Str(_) => None,
+            ItemType::Bytes(_) => None,
+            ItemType::I64(i64) => Some(*i64 as i128),
+            ItemType::I32(i32) => Some(*i32 as i128),
+            ItemType::U64(u64) => Some(*u64 as i128),
+            ItemType::U32(u32) => Some(*u32 as i128),
+        }
+    }
+
+    /// Key of the same type with the given value, which must fit in it
+    pub fn with_integer(&self, value: i128) -> ItemType {
+        match self {
+            ItemType::Str(_) => ItemType::Str(value.to_string()),
+            ItemType::Bytes(_) => ItemType::Bytes(value.to_string().into_bytes()),
+            ItemType::I64(_) => ItemType::I64(value as i64),
+            ItemType::I32(_) => ItemType::I32(value as i32),
+            ItemType::U64(_) => ItemType::U64(value as u64),
+            ItemType::U32(_) => ItemType::U32(value as u32),
+        }
+    }
+}
+
+/// Key of an input file with its value, and its location for messages
+pub type Pair = (ItemType, u64, String);
+
+/// Type the keys of the input file are parsed as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyType {
+    #[default]
+    Str,
+    /// Byte strings, which may not be valid UTF-8
+    Bytes,
+    I32,
+    I64,
+    U32,
+    U64,
+}
+
+impl FromStr for KeyType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "string" => Ok(KeyType::Str),
+            "bytes" => Ok(KeyType::Bytes),
+            "i32" => Ok(KeyType::I32),
+            "i64" => Ok(KeyType::I64),
+            "u32" => Ok(KeyType::U32),
+            "u64" => Ok(KeyType::U64),
+            _ => Err("Cannot find a corresponding key type. Expected: string, bytes, i32, i64, u32, u64"),
+        }
+    }
+}
+
+impl KeyType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyType::Str => "string",
+            KeyType::Bytes => "bytes",
+            KeyType::I32 => "i32",
+            KeyType::I64 => "i64",
+            KeyType::U32 => "u32",
+            KeyType::U64 => "u64",
+        }
+    }
+
+    /// Parses the bytes of a key, strings and integers are UTF-8 and integers are written in
+    /// decimal or hexadecimal (0x)
+    pub fn parse(&self, key: &[u8]) -> Result<ItemType, String> {
+        if *self == KeyType::Bytes {
+            return Ok(ItemType::Bytes(key.to_vec()));
+        }
+
+        let s = std::str::from_utf8(key).map_err(|e| {
+            format!(
+                "key {} is not valid UTF-8 (byte {}), use --key-type bytes for binary keys",
+                ItemType::Bytes(key.to_vec()),
+                e.valid_up_to() + 1
+            )
+        })?;
+        let out_of_range = || format!("{} key {} is out of range", self.name(), s.trim());
+
+        return match self {
+            KeyType::Str => Ok(ItemType::Str(s.to_string())),
+            KeyType::Bytes => Ok(ItemType::Bytes(key.to_vec())),
+            KeyType::I32 => i32::try_from(self.parse_integer(s)?).map(ItemType::I32).map_err(|_| out_of_range()),
+            KeyType::I64 => i64::try_from(self.parse_integer(s)?).map(ItemType::I64).map_err(|_| out_of_range()),
+            KeyType::U32 => u32::try_from(self.parse_integer(s)?).map(ItemType::U32).map_err(|_| out_of_range()),
+            KeyType::U64 => u64::try_from(self.parse_integer(s)?).map(ItemType::U64).map_err(|_| out_of_range()),
+        };
+    }
+
+    fn parse_integer(&self, s: &str) -> Result<i128, String> {
+        let s = s.trim();
+
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+
+        let parsed = match digits.strip_prefix("0x") {
+            Some(hex) => i128::from_str_radix(hex, 16),
+            None => digits.parse::<i128>(),
+        };
+
+        return parsed
+            .map(|value| if negative { -value } else { value })
+            .map_err(|e| format!("invalid {} key \"{}\": {}", self.name(), s, e));
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -73,220 +203,2270 @@ impl Item {
 pub struct Bucket {
     items: Vec<Item>,
     so_hash: SOHash,
+    pilot: u64,
 }
 
 impl Bucket {
     pub fn new(so_hash: SOHash) -> Bucket {
-        let mut bucket = Bucket::default();
-        bucket.so_hash = so_hash;
-
-        return bucket;
+        return Bucket {
+            items: Vec::new(),
+            so_hash,
+            pilot: 0,
+        };
     }
     pub fn so_hash(&self) -> &SOHash {
         return &self.so_hash;
     }
+
+    /// Index of the seed of the bucket in the sequence of tried seeds, stored by PTHash tables
+    pub fn pilot(&self) -> u64 {
+        return self.pilot;
+    }
 }
 
 type Buckets = Vec<Bucket>;
 
+// https://arxiv.org/pdf/2104.10402
+
+// Fraction of the keys sent to the dense buckets of PTHash tables
+const PTHASH_DENSE_KEYS: u64 = 6;
+const PTHASH_DENSE_KEYS_DIVISOR: u64 = 10;
+
+// Fraction of the buckets that are dense
+const PTHASH_DENSE_BUCKETS: f64 = 0.3;
+
+// Bytes of a cache line, the slot groups of k-perfect tables fill one by default
+pub const CACHE_LINE_BYTES: usize = 64;
+
+// Keys per bucket of k-perfect tables, a bucket is placed as soon as its groups have room left
+const KPERFECT_BUCKET_SIZE: f64 = 8.0;
+
+// Load factor of k-perfect tables, the last buckets could hardly find room in full groups
+const KPERFECT_LOAD_FACTOR: f64 = 0.97;
+
+// Keys per bucket of FKS tables, the expected number of slots is m (1 + 1 / bucket_size) + m
+const FKS_BUCKET_SIZE: f64 = 1.0;
+
+// Mixed with the seed of the table to get the seed of the fingerprints
+const FINGERPRINT_SEED_SALT: u64 = 0x66696e6765727072;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Compress, hash and displace: uniform buckets, each storing a random seed
+    #[default]
+    Chd,
+    /// Skewed buckets, each storing the smallest pilot that places it, see PTHash
+    PTHash,
+    /// Large buckets recursively split until leaves are placed by a bijection, see RecSplit
+    RecSplit,
+    /// Cascading bit arrays indexed by per-level hashes of 64-bits fingerprints, see BBHash
+    BBHash,
+    /// 3-uniform hypergraph peeling, with a 2-bits value per vertex, see BDZ
+    Bdz,
+    /// Order-preserving: the same hypergraph with a value in [0, m[ per vertex, so that the
+    /// i-th key of the input is stored in the i-th slot, see CHM
+    Chm,
+    /// Order-preserving for sorted keys: the slot of a key is its rank in lexicographic order,
+    /// found from the prefix shared by its bucket
+    Monotone,
+    /// Not a perfect hash: a static function from the keys to small values, which does not
+    /// store the keys and returns an arbitrary value for the other keys
+    Retrieval,
+    /// Up to k keys share a group of slots the size of a cache line, which the lookup scans:
+    /// larger buckets are placed with few seeds, for a faster construction and a smaller seeds table
+    KPerfect,
+    /// Two-level FKS scheme: each bucket gets its own table with as many slots as the square of
+    /// its number of keys, for the simplest lookup at the cost of about twice as many slots as keys
+    Fks,
+}
+
+impl FromStr for Algorithm {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chd" => Ok(Algorithm::Chd),
+            "pthash" => Ok(Algorithm::PTHash),
+            "recsplit" => Ok(Algorithm::RecSplit),
+            "bbhash" => Ok(Algorithm::BBHash),
+            "bdz" => Ok(Algorithm::Bdz),
+            "chm" => Ok(Algorithm::Chm),
+            "monotone" => Ok(Algorithm::Monotone),
+            "retrieval" => Ok(Algorithm::Retrieval),
+            "kperfect" => Ok(Algorithm::KPerfect),
+            "fks" => Ok(Algorithm::Fks),
+            _ => Err("Cannot find a corresponding algorithm. Expected: chd, pthash, recsplit, bbhash, bdz, chm, monotone, retrieval, kperfect, fks"),
+        }
+    }
+}
+
+impl Algorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Chd => "chd",
+            Algorithm::PTHash => "pthash",
+            Algorithm::RecSplit => "recsplit",
+            Algorithm::BBHash => "bbhash",
+            Algorithm::Bdz => "bdz",
+            Algorithm::Chm => "chm",
+            Algorithm::Monotone => "monotone",
+            Algorithm::Retrieval => "retrieval",
+            Algorithm::KPerfect => "kperfect",
+            Algorithm::Fks => "fks",
+        }
+    }
+
+    /// BBHash, BDZ, CHM, monotone and retrieval tables are built in a bounded number of passes
+    /// over the keys, instead of searching a seed for each bucket
+    pub fn searches_seeds(&self) -> bool {
+        return !matches!(
+            self,
+            Algorithm::BBHash | Algorithm::Bdz | Algorithm::Chm | Algorithm::Monotone | Algorithm::Retrieval
+        );
+    }
+
+    /// BBHash, BDZ, CHM, monotone and retrieval tables only use the first-order hash, with other
+    /// seeds
+    pub fn uses_second_order_hash(&self) -> bool {
+        return self.searches_seeds();
+    }
+
+    /// PTHash tables send most keys to a few dense buckets, the others spread them evenly
+    pub fn uses_skewed_buckets(&self) -> bool {
+        return *self == Algorithm::PTHash;
+    }
+
+    /// CHM and monotone tables map the keys to a given index: their input order, or their rank
+    pub fn preserves_order(&self) -> bool {
+        return matches!(self, Algorithm::Chm | Algorithm::Monotone);
+    }
+
+    /// Average bucket size used when none is given
+    pub fn default_bucket_size(&self) -> f64 {
+        match self {
+            Algorithm::Chd
+            | Algorithm::PTHash
+            | Algorithm::BBHash
+            | Algorithm::Bdz
+            | Algorithm::Chm
+            | Algorithm::Retrieval => 4.0,
+            Algorithm::Monotone => monotone::DEFAULT_BUCKET_SIZE,
+            Algorithm::KPerfect => KPERFECT_BUCKET_SIZE,
+            Algorithm::Fks => FKS_BUCKET_SIZE,
+            Algorithm::RecSplit => recsplit::DEFAULT_BUCKET_SIZE,
+        }
+    }
+
+    /// Load factor used when none is given
+    pub fn default_load_factor(&self) -> f64 {
+        match self {
+            Algorithm::Chd
+            | Algorithm::PTHash
+            | Algorithm::RecSplit
+            | Algorithm::BBHash
+            | Algorithm::Bdz
+            | Algorithm::Chm
+            | Algorithm::Monotone
+            | Algorithm::Retrieval
+            | Algorithm::Fks => 1.0,
+            Algorithm::KPerfect => KPERFECT_LOAD_FACTOR,
+        }
+    }
+
+    /// Seed given to the second-order hash for the given pilot. Second-order hashes force their
+    /// seed to be odd, hence only odd seeds are used for consecutive pilots to differ
+    pub fn pilot_seed(pilot: u64) -> u64 {
+        return pilot.wrapping_mul(2).wrapping_add(1);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildParams {
+    pub algorithm: Algorithm,
+    pub first_order_hash: String,
+    pub second_order_hash: String,
+    /// Average number of keys per bucket (lambda), the table uses ceil(m / bucket_size) buckets
+    pub bucket_size: f64,
+    /// Fraction of the slots filled during the search. PTHash tables remap the slots past
+    /// the number of keys to stay minimal, CHD tables are left non-minimal
+    pub load_factor: f64,
+    /// Maximum number of keys of the leaves of RecSplit trees
+    pub leaf_size: usize,
+    /// Number of parts the nodes of RecSplit trees are split into
+    pub fanout: usize,
+    /// Bits per key of the levels of BBHash tables
+    pub gamma: f64,
+    /// Type the keys of the input file are parsed as
+    pub key_type: KeyType,
+    /// Layout of delimited input files
+    pub input: InputOptions,
+    /// Serve the dense ranges of integer keys with direct-index tables, and only hash the others
+    pub dense_ranges: bool,
+    /// Maximum number of keys per slot group of k-perfect tables, as many slots as fit in a
+    /// cache line if None
+    pub group_size: Option<usize>,
+    /// Give up on a bucket after that many seeds, retry forever if None
+    pub max_attempts_per_bucket: Option<u64>,
+    /// Time budget of the search for a minimal table, including the fallbacks
+    pub timeout: Option<Duration>,
+    /// Try more buckets, other hashes and non-minimal tables when a limit is hit
+    pub fallbacks: bool,
+    /// Number of threads used for hashing and seed search
+    pub jobs: usize,
+    /// Seed of the seed search, the same seed gives the same table whatever the number of jobs
+    pub seed: Option<u64>,
+    pub verbose: bool,
+}
+
+impl Default for BuildParams {
+    fn default() -> Self {
+        return BuildParams {
+            algorithm: Algorithm::default(),
+            first_order_hash: "murmur3".to_string(),
+            second_order_hash: "xorshift".to_string(),
+            bucket_size: 4.0,
+            load_factor: 1.0,
+            leaf_size: recsplit::DEFAULT_LEAF_SIZE,
+            fanout: recsplit::DEFAULT_FANOUT,
+            gamma: bbhash::DEFAULT_GAMMA,
+            key_type: KeyType::default(),
+            input: InputOptions::default(),
+            dense_ranges: true,
+            group_size: None,
+            max_attempts_per_bucket: Some(1_000_000),
+            timeout: None,
+            fallbacks: true,
+            jobs: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            seed: None,
+            verbose: true,
+        };
+    }
+}
+
+// The bucket size is halved down to this floor before trying other hashes
+const MIN_FALLBACK_BUCKET_SIZE: f64 = 2.0;
+
+// Load factors of the non-minimal tables tried once the bucket size reached its floor
+const FALLBACK_LOAD_FACTORS: [f64; 3] = [0.9, 0.75, 0.5];
+
+// Last resort: single key buckets in a half-empty table, whose seed search always converges quickly
+const LAST_RESORT_BUCKET_SIZE: f64 = 1.0;
+const LAST_RESORT_LOAD_FACTOR: f64 = 0.5;
+
+/// A bucket for which no seed could be found within the limits
+#[derive(Debug, Clone)]
+pub struct StuckBucket {
+    pub bucket: usize,
+    pub keys: Vec<String>,
+    pub attempts: u64,
+    pub timed_out: bool,
+}
+
+impl Display for StuckBucket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const MAX_KEYS: usize = 8;
+
+        let mut keys = self.keys.iter().take(MAX_KEYS).cloned().collect::<Vec<String>>().join(", ");
+
+        if self.keys.len() > MAX_KEYS {
+            keys += ", ...";
+        }
+
+        write!(f, "bucket {} ({} keys: {}) ", self.bucket, self.keys.len(), keys)?;
+
+        if self.timed_out {
+            return write!(f, "was still unplaced when the timeout was reached ({} attempts)", self.attempts);
+        }
+
+        return write!(f, "could not be placed after {} attempts", self.attempts);
+    }
+}
+
+// One construction attempt of the fallback chain
+#[derive(Debug, Clone)]
+struct Stage {
+    algorithm: Algorithm,
+    first_order_hash: String,
+    second_order_hash: String,
+    bucket_size: f64,
+    load_factor: f64,
+    seed: u64,
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{}, average bucket size {}",
+            self.first_order_hash, self.second_order_hash, self.bucket_size
+        )?;
+
+        if self.load_factor < 1.0 && matches!(self.algorithm, Algorithm::Chd | Algorithm::KPerfect) {
+            write!(f, ", non-minimal table with load factor {}", self.load_factor)?;
+        } else if self.load_factor < 1.0 {
+            write!(f, ", load factor {}", self.load_factor)?;
+        }
+
+        return Ok(());
+    }
+}
+
+// Number of buckets searched ahead by each job
+const SPECULATIVE_BUCKETS_PER_JOB: usize = 16;
+
+// Number of buckets searched by each job between two progress updates, the buckets of FKS
+// tables are small and do not depend on each other
+const FKS_BUCKETS_PER_JOB: usize = 4096;
+
+// Hashes the keys, splitting them between jobs
+fn hash_items(keys: &[ItemType], fo_hash: &FOHash, jobs: usize) -> Vec<Item> {
+    if jobs <= 1 {
+        return keys.iter().map(|key| Item::new(key.clone(), fo_hash)).collect();
+    }
+
+    return std::thread::scope(|scope| {
+        let handles: Vec<_> = keys
+            .chunks(max(1, keys.len().div_ceil(jobs)))
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|key| Item::new(key.clone(), fo_hash))
+                        .collect::<Vec<Item>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("Hashing thread panicked"))
+            .collect()
+    });
+}
+
+// Hashes the keys to their fingerprints, splitting them between jobs
+fn hash_fingerprints(keys: &[ItemType], fo_hash: &FOHash, jobs: usize) -> Vec<u64> {
+    if jobs <= 1 {
+        return keys.iter().map(|key| fo_hash.hash(&key.hashable()).as_u64()).collect();
+    }
+
+    return std::thread::scope(|scope| {
+        let handles: Vec<_> = keys
+            .chunks(max(1, keys.len().div_ceil(jobs)))
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|key| fo_hash.hash(&key.hashable()).as_u64())
+                        .collect::<Vec<u64>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("Hashing thread panicked"))
+            .collect()
+    });
+}
+
+enum SearchResult {
+    Found { attempt: u64, positions: Vec<u32> },
+    Stuck { attempts: u64, timed_out: bool },
+}
+
+// Seed search of the buckets of a construction stage
+struct SeedSearch {
+    algorithm: Algorithm,
+    table_size: usize,
+    group_size: usize,
+    stage_seed: u64,
+    max_attempts: Option<u64>,
+    deadline: Option<Instant>,
+}
+
+impl SeedSearch {
+    // Seed tried by a bucket at the given attempt, the sequence only depends on the stage seed
+    fn seed(&self, bucket_index: usize, is_64bits: bool, attempt: u64) -> HashSeed {
+        let seed = match self.algorithm {
+            // Pilots are tried in order so that they stay small
+            Algorithm::PTHash => Algorithm::pilot_seed(attempt),
+            _ => splitmix64(splitmix64(self.stage_seed ^ bucket_index as u64).wrapping_add(attempt)),
+        };
+
+        if is_64bits {
+            return HashSeed::from(seed);
+        }
+
+        return HashSeed::from(seed as u32);
+    }
+
+    // Looks for the first seed, starting at the given attempt, placing the bucket in free slots.
+    // Keys hash to a group of slots and take its first free one
+    fn run(&self, bucket_index: usize, bucket: &Bucket, occupied: &[bool], start: u64) -> SearchResult {
+        let mut so_hash = bucket.so_hash.clone();
+        let mut positions: Vec<u32> = Vec::with_capacity(bucket.items.len());
+        let mut attempt = start;
+
+        loop {
+            let timed_out = self.deadline.is_some_and(|d| Instant::now() >= d);
+
+            if timed_out || self.max_attempts.is_some_and(|max| attempt >= max) {
+                return SearchResult::Stuck {
+                    attempts: attempt,
+                    timed_out,
+                };
+            }
+
+            so_hash.set_seed(self.seed(bucket_index, so_hash.is_64bits(), attempt));
+
+            positions.clear();
+
+            let mut collision = false;
+
+            for item in bucket.items.iter() {
+                let group = so_hash.hash(item.key()) % (self.table_size / self.group_size) as u32;
+                let first = group * self.group_size as u32;
+
+                let Some(pos) = (first..first + self.group_size as u32)
+                    .find(|&pos| !occupied[pos as usize] && !positions.contains(&pos))
+                else {
+                    collision = true;
+                    break;
+                };
+
+                positions.push(pos);
+            }
+
+            if !collision {
+                return SearchResult::Found { attempt, positions };
+            }
+
+            attempt += 1;
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct PHash {
+    algorithm: Algorithm,
     buckets: Buckets,
+    dense_buckets: usize,
+    remap: Vec<u32>,
+    range: usize,
+    recsplit: Option<RecSplit>,
+    bbhash: Option<BBHash>,
+    bdz: Option<Bdz>,
+    chm: Option<Chm>,
+    monotone: Option<Monotone>,
+    retrieval: Option<StaticFunction>,
+    fks: Option<Fks>,
+    dense: Option<DenseRanges>,
+    gperf: Option<Gperf>,
+    values: Option<Values>,
+    normalization: Normalization,
+    group_size: usize,
     fo_hash: FOHash,
     so_hash: SOHash,
     m: usize,
+    table_size: usize,
+    bucket_size: f64,
+    seed: u64,
 }
 
 impl PHash {
     fn new(
         first_order_hash: &str,
         second_order_hash: &str,
+        verbose: bool,
     ) -> Result<PHash, Box<dyn std::error::Error>> {
-        let mut phash = PHash::default();
-
-        phash.fo_hash = FOHash::from_str(first_order_hash)?;
-
-        phash.so_hash = SOHash::from_str(second_order_hash)?;
+        let mut phash = PHash {
+            fo_hash: FOHash::from_str(first_order_hash)?,
+            so_hash: SOHash::from_str(second_order_hash)?,
+            ..Default::default()
+        };
 
         if phash.fo_hash().is_64bits() && !phash.so_hash().is_64bits() {
-            println!("Second-order hash {} is not 64-bits, switching to mxf64", phash.so_hash().name());
+            if verbose {
+                println!("Second-order hash {} is not 64-bits, switching to mxf64", phash.so_hash().name());
+            }
+
             phash.so_hash = SOHash::MXF64(MXF64::default());
         }
 
         return Ok(phash);
     }
 
-    pub fn from_file(
-        file_path: &PathBuf,
-        first_order_hash: &str,
-        second_order_hash: &str,
-    ) -> Result<PHash, Box<dyn std::error::Error>> {
-        println!("Generating perfect hash for file: \"{}\"", file_path.display());
+    /// First-order hash of the build, fitted to the keys. The positions hash only reads the bytes
+    /// telling the keys apart, tables hashing 64-bits fingerprints of the keys need all of them
+    fn first_order_hash(keys: &[ItemType], params: &BuildParams) -> Result<FOHash, Box<dyn std::error::Error>> {
+        let mut fo_hash = FOHash::from_str(&params.first_order_hash)?;
+
+        if matches!(fo_hash, FOHash::Positions(_)) && !params.algorithm.searches_seeds() {
+            return Err(format!(
+                "{} tables rehash fingerprints of the keys with the first-order hash, the positions hash cannot tell them apart",
+                params.algorithm.name()
+            )
+            .into());
+        }
 
-        let mut phash = PHash::new(first_order_hash, second_order_hash)?;
+        // Fingerprints are rehashed into the table, 32-bits ones collide within a few tens of thousands of keys
+        if !params.algorithm.searches_seeds() && !fo_hash.is_64bits() {
+            if params.verbose {
+                println!(
+                    "First-order hash {} is not 64-bits, {} tables use 64-bits fingerprints, switching to xxhash64",
+                    fo_hash.name(),
+                    params.algorithm.name()
+                );
+            }
 
-        println!("First-order hash: {}", phash.fo_hash().name());
-        println!("Second-order hash: {}", phash.so_hash().name());
+            fo_hash = FOHash::XXHash64(XXHash64::default());
+        }
 
-        let file_content = std::fs::read_to_string(file_path).expect("Unable to read file");
+        fo_hash.fit(&keys.iter().map(|key| key.hashable()).collect::<Vec<Hashable>>())?;
 
-        let sep = Regex::new(r"([\n,]+)").expect("Invalid regex");
+        return Ok(fo_hash);
+    }
 
-        let mut m = 0;
+    // Keys with the same first-order hash cannot be told apart by any stage, whatever its seeds
+    fn check_collisions(keys: &[ItemType], fo_hash: &FOHash, jobs: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let hashes = hash_fingerprints(keys, fo_hash, jobs);
 
-        for _ in sep.find_iter(file_content.as_str()) {
-            m += 1;
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_unstable_by_key(|&i| hashes[i]);
+
+        for pair in order.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+
+            if hashes[a] != hashes[b] || keys[a] == keys[b] {
+                continue;
+            }
+
+            let advice = if fo_hash.is_64bits() { "" } else { ", use a 64-bits first-order hash such as xxhash64" };
+
+            return Err(format!(
+                "Keys {} and {} have the same {} hash ({}), the table cannot tell them apart{}",
+                keys[a],
+                keys[b],
+                fo_hash.name(),
+                hashes[a],
+                advice
+            )
+            .into());
         }
 
-        // We use m / 2 as the number of buckets. Could be changed to m / 4
-        let n = ((m as f64) * 0.1) as usize;
+        return Ok(());
+    }
 
-        println!("Using {n} buckets");
+    /// Reads the keys of an input file, with their location for messages
+    pub fn read_keys(
+        file: &InputFile,
+        key_type: KeyType,
+        input: &InputOptions,
+    ) -> Result<Vec<(ItemType, String)>, Box<dyn std::error::Error>> {
+        let name = file.name();
+
+        if let Some(gperf) = &file.gperf {
+            return gperf
+                .keywords
+                .iter()
+                .map(|keyword| {
+                    let location = format!("{name}:{}", keyword.line);
+                    let key = key_type
+                        .parse(keyword.name.as_bytes())
+                        .and_then(|key| input.normalization.apply(key))
+                        .map_err(|e| format!("{location}: {e}"))?;
+
+                    Ok((key, location))
+                })
+                .collect();
+        }
 
-        phash.buckets = vec![Bucket::new(phash.so_hash.clone()); n];
+        if input.format_of(&file.path) != InputFormat::Csv {
+            return PHash::read_json(file, input)?
+                .into_iter()
+                .map(|record| {
+                    let location = format!("{name}:{}", record.location);
+                    let key = PHash::parse_key(file, &record.key, key_type, input).map_err(|e| format!("{location}: {e}"))?;
 
-        for s in sep.split(file_content.as_str()).into_iter() {
-            if s.len() == 0 {
-                continue;
+                    Ok((key, location))
+                })
+                .collect();
+        }
+
+        let records = PHash::read_records(file, input)?;
+        let location = |field: &Field| format!("{name}:{}:{}", field.line, field.column);
+
+        // Without a key column, every field is a key
+        let fields: Vec<&Field> = match &input.key_column {
+            Some(column) => {
+                let index = records.index_of(column).map_err(|e| format!("{name}: {e}"))?;
+
+                records
+                    .records
+                    .iter()
+                    .map(|record| Records::field(record, index).map_err(|e| format!("{name}:{e}")))
+                    .collect::<Result<Vec<&Field>, String>>()?
             }
+            None => records.records.iter().flatten().collect(),
+        };
 
-            let item = Item::new(ItemType::Str(s.to_string()), &phash.fo_hash);
-            let item_key = (item.key() % n as u32) as usize;
+        let mut keys = Vec::with_capacity(fields.len());
 
-            // TODO: remove, can hurt performance
-            if phash.buckets[item_key].items.iter().find(|x| x.data == item.data).is_some() {
-                println!("Found duplicate: {}, removing it", item.data.to_string());
-                m -= 1;
-                continue;
+        for field in fields {
+            if field.value.is_empty() && !field.quoted {
+                return Err(format!("{}: empty key, quote it (\"\") to use the empty string as a key", location(field)).into());
             }
 
-            if let Some(found) = phash.buckets[item_key].items.iter().find(|x| x.key() == item.key()) {
-                println!("Found collision: {} / {} (key: {}), aborting",
-                         item.data.to_string(),
-                         found.data.to_string(),
-                         item.key());
-                m -= 1;
-                continue;
+            let key = PHash::parse_key(file, &field.value, key_type, input).map_err(|e| format!("{}: {}", location(field), e))?;
+            keys.push((key, location(field)));
+        }
+
+        return Ok(keys);
+    }
+
+    /// Reads the records of a key and a value, the value being an unsigned integer in decimal or
+    /// hexadecimal (0x). The value of a gperf keyword is its first field
+    pub fn read_pairs(
+        file: &InputFile,
+        key_type: KeyType,
+        input: &InputOptions,
+    ) -> Result<Vec<Pair>, Box<dyn std::error::Error>> {
+        let name = file.name();
+
+        let parse_value = |value: &str| match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => value.parse::<u64>(),
+        };
+
+        let mut pairs = Vec::new();
+
+        if let Some(gperf) = &file.gperf {
+            for keyword in gperf.keywords.iter() {
+                let location = format!("{name}:{}", keyword.line);
+
+                let value = keyword
+                    .fields
+                    .first()
+                    .ok_or_else(|| format!("{location}: expected a value after keyword \"{}\"", keyword.name))?;
+
+                let key = key_type
+                    .parse(keyword.name.as_bytes())
+                    .and_then(|key| input.normalization.apply(key))
+                    .map_err(|e| format!("{location}: {e}"))?;
+                let value = parse_value(value).map_err(|e| format!("{location}: invalid value \"{value}\": {e}"))?;
+
+                pairs.push((key, value, location));
             }
 
-            phash.buckets[item_key].items.push(item);
+            return Ok(pairs);
         }
 
-        println!("Found {m} items to process for the perfect hash table");
-        phash.m = m;
+        if input.format_of(&file.path) != InputFormat::Csv {
+            for record in PHash::read_json(file, input)?.into_iter() {
+                let location = format!("{name}:{}", record.location);
 
-        let mut sorted_buckets: Vec<&mut Bucket> = Vec::new();
-        sorted_buckets.extend(&mut phash.buckets);
-        sorted_buckets.sort_by_key(|item| std::cmp::Reverse(item.items.len()));
+                let value = match &record.value {
+                    Some(Value::Int(value)) if *value >= 0 => *value as u64,
+                    Some(_) => return Err(format!("{location}: expected an unsigned integer value").into()),
+                    None => return Err(format!("{location}: missing value of key \"{}\"", record.key).into()),
+                };
 
-        let mut occupied = vec![false; m as usize];
-        let total = sorted_buckets.iter().filter(|b| !b.items.is_empty()).count();
-        let mut done = 0;
+                let key = PHash::parse_key(file, &record.key, key_type, input).map_err(|e| format!("{location}: {e}"))?;
 
-        for bucket in sorted_buckets.iter_mut() {
-            if bucket.items.len() == 0 {
-                continue;
+                pairs.push((key, value, location));
             }
 
-            let mut collision = true;
+            return Ok(pairs);
+        }
 
-            // println!("{:?}", bucket.items);
+        let records = PHash::read_records(file, input)?;
+        let location = |field: &Field| format!("{name}:{}:{}", field.line, field.column);
 
-            let mut candidate_pos: Vec<u32> = Vec::new();
+        let key_index = match &input.key_column {
+            Some(column) => records.index_of(column).map_err(|e| format!("{name}: {e}"))?,
+            None => 0,
+        };
 
-            while collision {
-                if bucket.so_hash.is_64bits() {
-                    bucket.so_hash.set_seed(rand::random::<u64>().into());
-                } else {
-                    bucket.so_hash.set_seed(rand::random::<u32>().into());
-                }
+        let value_index = match &input.value_column {
+            Some(column) => records.index_of(column).map_err(|e| format!("{name}: {e}"))?,
+            None => key_index + 1,
+        };
 
-                collision = false;
-                candidate_pos.clear();
+        for record in records.records.iter() {
+            let key = Records::field(record, key_index).map_err(|e| format!("{name}:{e}"))?;
+            let value = Records::field(record, value_index).map_err(|e| format!("{name}:{e}"))?;
 
-                for item in bucket.items.iter_mut() {
-                    let pos = bucket.so_hash.hash(item.key()) % m as u32;
+            if key.value.is_empty() && !key.quoted {
+                return Err(format!("{}: empty key, quote it (\"\") to use the empty string as a key", location(key)).into());
+            }
 
-                    if occupied[pos as usize] {
-                        collision = true;
-                        break;
-                    }
+            let parsed = parse_value(value.value.trim())
+                .map_err(|e| format!("{}: invalid value \"{}\": {}", location(value), value.value, e))?;
 
-                    if candidate_pos.iter().find(|&x| *x == pos).is_some() {
-                        collision = true;
-                        break;
-                    }
+            pairs.push((PHash::parse_key(file, &key.value, key_type, input).map_err(|e| format!("{}: {}", location(key), e))?, parsed, location(key)));
+        }
 
-                    candidate_pos.push(pos);
+        return Ok(pairs);
+    }
 
-                    item.final_pos = pos;
-                }
+    /// Reads the typed values of the keys of JSON files, None if the records have no value
+    pub fn read_values(
+        file: &InputFile,
+        key_type: KeyType,
+        input: &InputOptions,
+    ) -> Result<Option<Values>, Box<dyn std::error::Error>> {
+        if file.gperf.is_some() || input.format_of(&file.path) == InputFormat::Csv {
+            return Ok(None);
+        }
+
+        let records = PHash::read_json(file, input)?;
+
+        if records.iter().all(|record| record.value.is_none()) {
+            return Ok(None);
+        }
+
+        let mut value_type: Option<ValueType> = None;
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+
+        for record in records.into_iter() {
+            let location = format!("{}:{}", file.name(), record.location);
+
+            let value = record
+                .value
+                .ok_or_else(|| format!("{location}: missing value of key \"{}\", the other keys have one", record.key))?;
+            let other = value.value_type().map_err(|e| format!("{location}: {e}"))?;
+
+            value_type = Some(match value_type {
+                Some(value_type) => value_type.unify(&other).ok_or_else(|| {
+                    format!("{location}: {} value of key \"{}\", the previous values are of type {}", other.name(), record.key, value_type.name())
+                })?,
+                None => other,
+            });
+
+            keys.push(PHash::parse_key(file, &record.key, key_type, input).map_err(|e| format!("{location}: {e}"))?);
+            values.push(value);
+        }
 
-                if !collision {
-                    for pos in candidate_pos.iter() {
-                        occupied[*pos as usize] = true;
+        return Ok(value_type.map(|value_type| Values { value_type, keys, values }));
+    }
+
+    /// Reads the keys of the input files into one keyset, in order. Duplicate keys are reported
+    /// with their locations, only their first occurrence is kept
+    pub fn read_all_keys(
+        files: &[InputFile],
+        key_type: KeyType,
+        input: &InputOptions,
+        verbose: bool,
+    ) -> Result<Vec<ItemType>, Box<dyn std::error::Error>> {
+        let mut first_locations: HashMap<Hashable, String> = HashMap::new();
+        let mut keys = Vec::new();
+
+        for file in files.iter() {
+            for (key, location) in PHash::read_keys(file, key_type, input)? {
+                match first_locations.get(&key.hashable()) {
+                    Some(first) if verbose => println!("Found duplicate: {key} at {location}, first read at {first}, removing it"),
+                    Some(_) => {}
+                    None => {
+                        first_locations.insert(key.hashable(), location);
+                        keys.push(key);
                     }
                 }
             }
+        }
 
-            done += 1;
+        return Ok(keys);
+    }
 
-            if done == total || done % max(1, total.strict_div_euclid(1000)) == 0 {
-                print!(
-                    "\rProgress: {}/{} ({:.1}%)   ",
-                    done,
-                    total,
-                    (done as f64 / total as f64) * 100.0
-                );
+    /// Reads the keys and values of the input files, in order. Duplicate keys are reported with
+    /// their locations, and are an error when their values differ
+    pub fn read_all_pairs(
+        files: &[InputFile],
+        key_type: KeyType,
+        input: &InputOptions,
+        verbose: bool,
+    ) -> Result<(Vec<ItemType>, Vec<u64>), Box<dyn std::error::Error>> {
+        let mut first_pairs: HashMap<Hashable, (u64, String)> = HashMap::new();
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+
+        for file in files.iter() {
+            for (key, value, location) in PHash::read_pairs(file, key_type, input)? {
+                match first_pairs.get(&key.hashable()) {
+                    Some((first_value, first)) if *first_value != value => {
+                        return Err(format!("Key {key} has two values: {first_value} at {first} and {value} at {location}").into());
+                    }
+                    Some((_, first)) if verbose => println!("Found duplicate: {key} at {location}, first read at {first}, removing it"),
+                    Some(_) => {}
+                    None => {
+                        first_pairs.insert(key.hashable(), (value, location));
+                        keys.push(key);
+                        values.push(value);
+                    }
+                }
             }
         }
 
-        println!("");
-
-        return Ok(phash);
+        return Ok((keys, values));
     }
 
-    pub fn m(&self) -> usize {
-        return self.m;
-    }
+    /// Reads the values of the keys of the input files, the first value of duplicate keys being
+    /// kept. Either all the files have values, or none of them
+    pub fn read_all_values(
+        files: &[InputFile],
+        key_type: KeyType,
+        input: &InputOptions,
+    ) -> Result<Option<Values>, Box<dyn std::error::Error>> {
+        let mut all: Option<Values> = None;
+        let mut without_values = Vec::new();
+
+        for file in files.iter() {
+            let Some(values) = PHash::read_values(file, key_type, input)? else {
+                without_values.push(file.name());
+                continue;
+            };
+
+            all = Some(match all {
+                Some(mut all) => {
+                    all.value_type = all.value_type.unify(&values.value_type).ok_or_else(|| {
+                        format!(
+                            "{}: values of type {}, the values of the previous files are of type {}",
+                            file.name(),
+                            values.value_type.name(),
+                            all.value_type.name()
+                        )
+                    })?;
+
+                    all.keys.extend(values.keys);
+                    all.values.extend(values.values);
+                    all
+                }
+                None => values,
+            });
+        }
 
-    pub fn fo_hash(&self) -> &FOHash {
-        return &self.fo_hash;
+        let Some(mut all) = all else {
+            return Ok(None);
+        };
+
+        if let Some(name) = without_values.first() {
+            return Err(format!("{name}: the keys have no value, the keys of the other input files have one").into());
+        }
+
+        let mut seen = HashSet::new();
+        let (keys, values) = all
+            .keys
+            .into_iter()
+            .zip(all.values)
+            .filter(|(key, _)| seen.insert(key.hashable()))
+            .unzip();
+
+        all.keys = keys;
+        all.values = values;
+
+        return Ok(Some(all));
     }
 
-    pub fn so_hash(&self) -> &SOHash {
-        return &self.so_hash;
+    // Key of a field of an input file, decoded by the key encoding and normalized
+    fn parse_key(file: &InputFile, text: &str, key_type: KeyType, input: &InputOptions) -> Result<ItemType, String> {
+        let bytes = input.key_encoding.decode(text, file)?;
+
+        return input.normalization.apply(key_type.parse(&bytes)?);
     }
 
-    pub fn buckets(&self) -> &Buckets {
-        return &self.buckets;
+    // Records of a JSON or JSON Lines file
+    fn read_json(file: &InputFile, input: &InputOptions) -> Result<Vec<JsonRecord>, Box<dyn std::error::Error>> {
+        file.text().map_err(|e| format!("{}:{}", file.name(), e))?;
+
+        let records = match input.format_of(&file.path) {
+            InputFormat::Jsonl => input::parse_jsonl(&file.content, input),
+            _ => input::parse_json(&file.content, input),
+        };
+
+        return Ok(records.map_err(|e| format!("{}:{}", file.name(), e))?);
     }
 
-    pub fn first_bucket(&self) -> Option<&Bucket> {
-        return self.buckets.first();
+    // Records of a delimited file, comma or tab separated
+    fn read_records(file: &InputFile, input: &InputOptions) -> Result<Records, Box<dyn std::error::Error>> {
+        let records = Records::parse(&file.content, input, input.delimiter_of(&file.path))
+            .map_err(|e| format!("{}:{}", file.name(), e))?;
+
+        return Ok(records);
     }
 
-    pub fn first_item(&self) -> Option<&Item> {
-        for bucket in self.buckets.iter() {
-            if bucket.items.is_empty() {
-                continue;
-            }
+    /// Builds the table of the keys of the input files, merged into one keyset
+    pub fn from_files(
+        files: &[InputFile],
+        params: &BuildParams,
+    ) -> Result<PHash, Box<dyn std::error::Error>> {
+        let names: Vec<String> = files.iter().map(|file| file.name()).collect();
 
-            return bucket.items.first();
+        match names.len() {
+            1 => println!("Generating perfect hash for file: \"{}\"", names[0]),
+            _ => println!("Generating perfect hash for files: \"{}\"", names.join("\", \"")),
         }
 
-        return None;
-    }
+        // The code and struct of gperf files are generated along with the table, they cannot be
+        // merged with other files
+        let gperf_file = files.iter().find(|file| file.gperf.is_some());
 
-    pub fn items(&self) -> Vec<&Item> {
-        let mut result = vec![None; self.m];
+        if let Some(file) = gperf_file
+            && files.len() > 1
+        {
+            return Err(format!("{}: gperf files cannot be merged with other input files", file.name()).into());
+        }
 
-        for bucket in self.buckets.iter() {
-            for item in bucket.items.iter() {
-                result[item.final_pos as usize] = Some(item);
+        if params.verbose && !params.input.normalization.is_empty() {
+            println!("Normalizing the keys: {}", params.input.normalization.describe());
+        }
+
+        let mut phash = PHash::from_files_keys(files, params)?;
+        phash.normalization = params.input.normalization.clone();
+
+        if let Some(gperf) = gperf_file.and_then(|file| file.gperf.clone()) {
+
+            if params.verbose && !gperf.ignored_options.is_empty() {
+                println!("Ignoring gperf options tuning its own lookup: {}", gperf.ignored_options.join(", "));
+            }
+
+            phash.gperf = Some(gperf);
+        }
+
+        // Retrieval tables map the keys to their values without storing them
+        if params.algorithm != Algorithm::Retrieval {
+            phash.values = PHash::read_all_values(files, params.key_type, &params.input)?;
+
+            if params.verbose
+                && let Some(values) = phash.values.as_ref()
+            {
+                println!("Read {} values of type {}", values.values.len(), values.value_type.name());
             }
         }
 
-        return result.into_iter().flatten().collect();
+        return Ok(phash);
+    }
+
+    fn from_files_keys(
+        files: &[InputFile],
+        params: &BuildParams,
+    ) -> Result<PHash, Box<dyn std::error::Error>> {
+        if params.algorithm == Algorithm::Retrieval {
+            let (keys, values) = PHash::read_all_pairs(files, params.key_type, &params.input, params.verbose)?;
+
+            return PHash::from_pairs(keys, values, params);
+        }
+
+        let keys = PHash::read_all_keys(files, params.key_type, &params.input, params.verbose)?;
+
+        // Order-preserving tables index all the keys, dense ranges would leave holes in the order
+        if !params.dense_ranges || params.algorithm.preserves_order() {
+            return PHash::from_keys(&keys, params);
+        }
+
+        let (dense, sparse) = DenseRanges::split(keys);
+
+        if params.verbose {
+            match &dense {
+                Some(dense) => {
+                    let ranges: Vec<String> = dense
+                        .ranges
+                        .iter()
+                        .map(|range| format!("{} to {}", range.start, range.start + range.size as i128 - 1))
+                        .collect();
+
+                    println!(
+                        "Found {} dense range(s) of keys ({}): {} keys served by direct-index tables, {} keys left for the perfect hash",
+                        dense.ranges.len(),
+                        ranges.join(", "),
+                        dense.num_keys(),
+                        sparse.len()
+                    );
+                }
+                None if !matches!(params.key_type, KeyType::Str | KeyType::Bytes) => {
+                    println!("No dense range of keys found, hashing all the keys");
+                }
+                None => {}
+            }
+        }
+
+        let mut phash = PHash::from_keys(&sparse, params)?;
+        phash.dense = dense;
+
+        return Ok(phash);
+    }
+
+    /// Builds a retrieval table, mapping each key to its value without storing the keys
+    pub fn from_pairs(
+        keys: Vec<ItemType>,
+        values: Vec<u64>,
+        params: &BuildParams,
+    ) -> Result<PHash, Box<dyn std::error::Error>> {
+        let seed = params.seed.unwrap_or_else(rand::random::<u64>);
+
+        if params.verbose {
+            println!("Seed: {seed}");
+        }
+
+        let fo_hash = PHash::first_order_hash(&keys, params)?;
+
+        // Built with the selected first-order hash, so that a 64-bits one gets a 64-bits second-order hash
+        let mut phash = PHash::new(fo_hash.name(), &params.second_order_hash, false)?;
+
+        phash.fo_hash = fo_hash;
+
+        if params.verbose {
+            println!("First-order hash: {}", phash.fo_hash().name());
+        }
+
+        phash.algorithm = Algorithm::Retrieval;
+        phash.seed = seed;
+        phash.bucket_size = params.bucket_size;
+
+        let fingerprints = hash_fingerprints(&keys, &phash.fo_hash, max(1, params.jobs));
+
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_by_key(|&i| fingerprints[i]);
+
+        let mut kept: Vec<usize> = Vec::with_capacity(keys.len());
+
+        // Unlike perfect hash tables, keys are not compared on lookup, so a collision would
+        // silently return the value of the other key
+        for i in order {
+            if let Some(&last) = kept.last()
+                && fingerprints[last] == fingerprints[i]
+            {
+                if values[last] != values[i] {
+                    if keys[last] == keys[i] {
+                        return Err(format!("Key {} has two values: {} and {}", keys[i], values[last], values[i]).into());
+                    }
+
+                    return Err(format!(
+                        "Keys {} and {} have the same first-order hash, use a 64-bits first-order hash",
+                        keys[last], keys[i]
+                    )
+                    .into());
+                }
+
+                if params.verbose && keys[last] == keys[i] {
+                    println!("Found duplicate: {}, removing it", keys[i]);
+                }
+
+                continue;
+            }
+
+            kept.push(i);
+        }
+
+        let unique: Vec<u64> = kept.iter().map(|&i| fingerprints[i]).collect();
+        let unique_values: Vec<u64> = kept.iter().map(|&i| values[i]).collect();
+
+        let retrieval = StaticFunction::new(&unique, &unique_values, &phash.fo_hash, seed).map_err(|attempts| {
+            format!("Cannot build the retrieval table, no hypergraph could be peeled after {attempts} attempts")
+        })?;
+
+        if params.verbose {
+            println!(
+                "Found {} keys, values of {} bits ({:.2} bits/key)",
+                kept.len(),
+                retrieval.width,
+                retrieval.size_bits() as f64 / max(1, kept.len()) as f64
+            );
+        }
+
+        let is_64bits = phash.fo_hash.is_64bits();
+
+        let items = kept
+            .into_iter()
+            .enumerate()
+            .map(|(pos, i)| Item {
+                data: keys[i].clone(),
+                key: if is_64bits { HashKey::from(fingerprints[i]) } else { HashKey::from(fingerprints[i] as u32) },
+                final_pos: pos as u32,
+            })
+            .collect::<Vec<Item>>();
+
+        phash.m = items.len();
+        phash.table_size = items.len();
+        phash.range = items.len();
+        phash.dense_buckets = 1;
+        phash.buckets = vec![Bucket {
+            items,
+            so_hash: phash.so_hash.clone(),
+            pilot: 0,
+        }];
+        phash.retrieval = Some(retrieval);
+
+        return Ok(phash);
+    }
+
+    // Lists the construction attempts: the requested parameters, then more buckets,
+    // then other second-order hashes, then non-minimal tables, then the last resort
+    fn stages(params: &BuildParams, seed: u64) -> Vec<Stage> {
+        let first = Stage {
+            algorithm: params.algorithm,
+            first_order_hash: params.first_order_hash.clone(),
+            second_order_hash: params.second_order_hash.clone(),
+            bucket_size: params.bucket_size,
+            load_factor: params.load_factor,
+            seed,
+        };
+
+        let mut stages = vec![first.clone()];
+
+        // BBHash, BDZ, CHM and monotone tables do not search for seeds, there is nothing to fall back on
+        if !params.fallbacks || !params.algorithm.searches_seeds() {
+            return stages;
+        }
+
+        let mut bucket_size = params.bucket_size;
+
+        while bucket_size > MIN_FALLBACK_BUCKET_SIZE {
+            bucket_size = f64::max(bucket_size / 2.0, MIN_FALLBACK_BUCKET_SIZE);
+
+            stages.push(Stage {
+                bucket_size,
+                ..first.clone()
+            });
+        }
+
+        let fo_is_64bits = FOHash::from_str(&params.first_order_hash).is_ok_and(|h| h.is_64bits());
+
+        for so_hash in SOHash::all().iter() {
+            if so_hash.name() == params.second_order_hash || (fo_is_64bits && !so_hash.is_64bits()) {
+                continue;
+            }
+
+            stages.push(Stage {
+                second_order_hash: so_hash.name().to_string(),
+                bucket_size,
+                ..first.clone()
+            });
+        }
+
+        // RecSplit tables are always minimal, FKS tables are sized by their buckets
+        let has_load_factor = !matches!(params.algorithm, Algorithm::RecSplit | Algorithm::Fks);
+
+        if has_load_factor {
+            for load_factor in FALLBACK_LOAD_FACTORS {
+                if load_factor >= params.load_factor {
+                    continue;
+                }
+
+                stages.push(Stage {
+                    bucket_size,
+                    load_factor,
+                    ..first.clone()
+                });
+            }
+        }
+
+        let last_resort = Stage {
+            bucket_size: f64::min(bucket_size, LAST_RESORT_BUCKET_SIZE),
+            load_factor: if has_load_factor { f64::min(params.load_factor, LAST_RESORT_LOAD_FACTOR) } else { params.load_factor },
+            ..first.clone()
+        };
+
+        let last = stages.last().expect("The first stage is always tried");
+
+        if last.bucket_size != last_resort.bucket_size || last.load_factor != last_resort.load_factor {
+            stages.push(last_resort);
+        }
+
+        // Each stage searches its own sequence of seeds
+        for (i, stage) in stages.iter_mut().enumerate() {
+            stage.seed = splitmix64(seed.wrapping_add(i as u64));
+        }
+
+        return stages;
+    }
+
+    pub fn from_keys(
+        keys: &[ItemType],
+        params: &BuildParams,
+    ) -> Result<PHash, Box<dyn std::error::Error>> {
+        if params.bucket_size.is_nan() || params.bucket_size <= 0.0 {
+            return Err(format!("Invalid average bucket size {}, it must be greater than 0", params.bucket_size).into());
+        }
+
+        if params.load_factor.is_nan() || params.load_factor <= 0.0 || params.load_factor > 1.0 {
+            return Err(format!("Invalid load factor {}, it must be in ]0, 1]", params.load_factor).into());
+        }
+
+        if params.algorithm == Algorithm::BBHash && (params.gamma.is_nan() || params.gamma < 1.0) {
+            return Err(format!("Invalid gamma {}, it must be at least 1", params.gamma).into());
+        }
+
+        if params.algorithm == Algorithm::Retrieval {
+            return Err("Retrieval tables map keys to values, they are built from key,value pairs".into());
+        }
+
+        if params.group_size == Some(0) {
+            return Err("Invalid group size 0, it must be at least 1".into());
+        }
+
+        if params.algorithm == Algorithm::Monotone && keys.iter().any(|key| key.as_integer().is_some()) {
+            return Err("Monotone tables rank the keys in lexicographic order, they only support string and byte string keys".into());
+        }
+
+        if params.algorithm == Algorithm::Fks && params.load_factor < 1.0 {
+            return Err("FKS tables are sized by the number of keys of their buckets, they do not support a load factor".into());
+        }
+
+        if params.algorithm == Algorithm::RecSplit {
+            if params.load_factor < 1.0 {
+                return Err("RecSplit tables are always minimal, they do not support a load factor".into());
+            }
+
+            if !(2..=recsplit::MAX_LEAF_SIZE).contains(&params.leaf_size) {
+                return Err(format!("Invalid leaf size {}, it must be in [2, {}]", params.leaf_size, recsplit::MAX_LEAF_SIZE).into());
+            }
+
+            if params.fanout < 2 {
+                return Err(format!("Invalid fanout {}, it must be at least 2", params.fanout).into());
+            }
+        }
+
+        let deadline = params.timeout.map(|t| Instant::now() + t);
+
+        let seed = params.seed.unwrap_or_else(rand::random::<u64>);
+
+        if params.verbose {
+            println!("Seed: {seed}");
+        }
+
+        let stages = PHash::stages(params, seed);
+        let last_stage = stages.len() - 1;
+
+        // Selected once, the key positions do not depend on the stage
+        let mut fo_hash = PHash::first_order_hash(keys, params)?;
+
+        // 32-bits hashes collide within a few hundred thousand keys, fall back on a 64-bits one
+        if let Err(e) = PHash::check_collisions(keys, &fo_hash, max(1, params.jobs)) {
+            if !params.fallbacks || fo_hash.is_64bits() {
+                return Err(e);
+            }
+
+            if params.verbose {
+                println!("{e}, switching to xxhash64");
+            }
+
+            fo_hash = FOHash::XXHash64(XXHash64::default());
+            PHash::check_collisions(keys, &fo_hash, max(1, params.jobs))?;
+        }
+
+        let mut stuck = None;
+
+        for (i, stage) in stages.iter().enumerate() {
+            let timed_out = deadline.is_some_and(|d| Instant::now() >= d);
+
+            // Once the time budget is spent, go straight to the last resort which is not time-bounded,
+            // and converges quickly
+            if timed_out && i != last_stage {
+                continue;
+            }
+
+            if params.verbose && i > 0 {
+                println!("Retrying with {}", stage);
+            }
+
+            let uses_so_hash = stage.algorithm.uses_second_order_hash();
+
+            // Built with the selected first-order hash, so that a 64-bits one gets a 64-bits second-order hash
+            let mut phash = PHash::new(
+                fo_hash.name(),
+                &stage.second_order_hash,
+                params.verbose && i == 0 && uses_so_hash,
+            )?;
+
+            phash.fo_hash = fo_hash.clone();
+
+            if params.verbose && i == 0 {
+                println!("First-order hash: {}", phash.fo_hash().name());
+
+                if let FOHash::Positions(positions) = phash.fo_hash() {
+                    println!("Key positions: {}", positions.describe());
+                }
+
+                if uses_so_hash {
+                    println!("Second-order hash: {}", phash.so_hash().name());
+                }
+            }
+
+            let stage_deadline = if i == last_stage { None } else { deadline };
+
+            match phash.build(
+                keys,
+                stage,
+                params,
+                stage_deadline,
+                params.verbose && i == 0,
+            ) {
+                Ok(()) => return Ok(phash),
+                Err(e) => {
+                    if params.verbose {
+                        println!();
+                        println!("With {}, {}", stage, e);
+                    }
+
+                    stuck = Some(e);
+                }
+            }
+        }
+
+        let stuck = stuck.expect("At least one construction stage is always tried");
+
+        return Err(format!("Cannot build the perfect hash table, {}", stuck).into());
+    }
+
+    fn build(
+        &mut self,
+        keys: &[ItemType],
+        stage: &Stage,
+        params: &BuildParams,
+        deadline: Option<Instant>,
+        verbose: bool,
+    ) -> Result<(), StuckBucket> {
+        let jobs = max(1, params.jobs);
+
+        if !stage.algorithm.searches_seeds() {
+            return self.build_fingerprinted(keys, stage, params, verbose);
+        }
+
+        // At least one bucket, so small keysets do not end up with zero buckets. PTHash tables
+        // need both a dense and a sparse bucket
+        let min_buckets = if stage.algorithm == Algorithm::PTHash { 2 } else { 1 };
+        let n = max(min_buckets, ((keys.len() as f64) / stage.bucket_size).ceil() as usize);
+
+        if verbose {
+            println!("Using {n} buckets");
+        }
+
+        self.buckets = vec![Bucket::new(self.so_hash.clone()); n];
+        self.bucket_size = stage.bucket_size;
+        self.seed = stage.seed;
+        self.algorithm = stage.algorithm;
+        self.dense_buckets = match stage.algorithm.uses_skewed_buckets() {
+            true => ((n as f64 * PTHASH_DENSE_BUCKETS) as usize).clamp(1, n - 1),
+            false => n,
+        };
+
+        let mut m = 0;
+
+        for item in hash_items(keys, &self.fo_hash, jobs).into_iter() {
+            let item_key = self.bucket_of(item.key());
+
+            // TODO: remove, can hurt performance
+            if self.buckets[item_key].items.iter().any(|x| x.data == item.data) {
+                if verbose {
+                    println!("Found duplicate: {}, removing it", item.data);
+                }
+
+                continue;
+            }
+
+            self.buckets[item_key].items.push(item);
+            m += 1;
+        }
+
+        if verbose {
+            println!("Found {m} items to process for the perfect hash table");
+        }
+
+        self.m = m;
+
+        // Slots are allocated by groups, a single slot per group except for k-perfect tables
+        self.group_size = match stage.algorithm {
+            Algorithm::KPerfect => params.group_size.unwrap_or_else(|| PHash::default_group_size(self.first_item())),
+            _ => 1,
+        };
+
+        // Non-minimal tables have more slots than keys
+        let groups = (m as f64 / (self.group_size as f64 * stage.load_factor)).ceil() as usize;
+        let table_size = max(m, groups * self.group_size);
+        self.table_size = table_size;
+        self.range = table_size;
+
+        if self.algorithm == Algorithm::RecSplit {
+            return self.build_recsplit(stage, params, deadline, verbose);
+        }
+
+        if self.algorithm == Algorithm::Fks {
+            return self.build_fks(stage, params, deadline, verbose);
+        }
+
+        let mut sorted_buckets: Vec<usize> = (0..n).filter(|&i| !self.buckets[i].items.is_empty()).collect();
+        sorted_buckets.sort_by_key(|&i| std::cmp::Reverse(self.buckets[i].items.len()));
+
+        let search = SeedSearch {
+            algorithm: stage.algorithm,
+            table_size,
+            group_size: self.group_size,
+            stage_seed: stage.seed,
+            max_attempts: params.max_attempts_per_bucket,
+            deadline,
+        };
+
+        let mut occupied = vec![false; table_size];
+        let total = sorted_buckets.len();
+        let mut done = 0;
+
+        // Buckets are searched speculatively by batches against the current occupied slots,
+        // and committed in order. As every bucket tries the same sequence of seeds, the first seed
+        // that fits in the snapshot is also the first one that fits once the previous buckets of
+        // the batch are committed, unless it collides with them, in which case the search resumes
+        // from there. The result does not depend on the number of jobs.
+        let batch_size = if jobs > 1 { jobs * SPECULATIVE_BUCKETS_PER_JOB } else { 1 };
+
+        for batch in sorted_buckets.chunks(batch_size) {
+            let results: Vec<SearchResult> = if jobs > 1 {
+                let buckets = &self.buckets;
+                let occupied = &occupied;
+                let search = &search;
+
+                std::thread::scope(|scope| {
+                    let handles: Vec<_> = batch
+                        .chunks(batch.len().div_ceil(jobs))
+                        .map(|chunk| {
+                            scope.spawn(move || {
+                                chunk
+                                    .iter()
+                                    .map(|&i| search.run(i, &buckets[i], occupied, 0))
+                                    .collect::<Vec<SearchResult>>()
+                            })
+                        })
+                        .collect();
+
+                    handles
+                        .into_iter()
+                        .flat_map(|h| h.join().expect("Seed search thread panicked"))
+                        .collect()
+                })
+            } else {
+                batch
+                    .iter()
+                    .map(|&i| search.run(i, &self.buckets[i], &occupied, 0))
+                    .collect()
+            };
+
+            for (&bucket_index, result) in batch.iter().zip(results) {
+                let mut result = result;
+
+                // The same seed may still fit in other slots of the groups of k-perfect tables
+                if let SearchResult::Found { attempt, ref positions } = result
+                    && positions.iter().any(|&pos| occupied[pos as usize])
+                {
+                    result = search.run(bucket_index, &self.buckets[bucket_index], &occupied, attempt);
+                }
+
+                let bucket = &mut self.buckets[bucket_index];
+
+                match result {
+                    SearchResult::Found { attempt, positions } => {
+                        bucket.so_hash.set_seed(search.seed(bucket_index, bucket.so_hash.is_64bits(), attempt));
+                        bucket.pilot = attempt;
+
+                        for (item, pos) in bucket.items.iter_mut().zip(positions) {
+                            occupied[pos as usize] = true;
+                            item.final_pos = pos;
+                        }
+                    }
+                    SearchResult::Stuck { attempts, timed_out } => {
+                        return Err(StuckBucket {
+                            bucket: bucket_index,
+                            keys: bucket.items.iter().map(|item| item.data.to_string()).collect(),
+                            attempts,
+                            timed_out,
+                        });
+                    }
+                }
+
+                done += 1;
+
+                if verbose && (done == total || done % max(1, total.strict_div_euclid(1000)) == 0) {
+                    print!(
+                        "\rProgress: {}/{} ({:.1}%)   ",
+                        done,
+                        total,
+                        (done as f64 / total as f64) * 100.0
+                    );
+                }
+            }
+        }
+
+        if verbose {
+            println!();
+        }
+
+        if self.algorithm == Algorithm::PTHash && table_size > m {
+            self.remap_slots(&occupied);
+        }
+
+        return Ok(());
+    }
+
+    // Only keeps the 64-bits fingerprints of the keys while building the BBHash levels or the
+    // BDZ, CHM and monotone hypergraphs, the keys are stored in a single bucket, in slot order, once placed
+    fn build_fingerprinted(
+        &mut self,
+        keys: &[ItemType],
+        stage: &Stage,
+        params: &BuildParams,
+        verbose: bool,
+    ) -> Result<(), StuckBucket> {
+        let jobs = max(1, params.jobs);
+
+        self.seed = stage.seed;
+        self.algorithm = stage.algorithm;
+        self.bucket_size = stage.bucket_size;
+
+        let fingerprints = hash_fingerprints(keys, &self.fo_hash, jobs);
+
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_by_key(|&i| fingerprints[i]);
+
+        let mut kept: Vec<usize> = Vec::with_capacity(keys.len());
+
+        // Colliding keys were rejected before the first stage, equal fingerprints are duplicates
+        for i in order {
+            if let Some(&last) = kept.last()
+                && fingerprints[last] == fingerprints[i]
+            {
+                if verbose {
+                    println!("Found duplicate: {}, removing it", keys[i]);
+                }
+
+                continue;
+            }
+
+            kept.push(i);
+        }
+
+        let m = kept.len();
+
+        if verbose {
+            println!("Found {m} items to process for the perfect hash table");
+        }
+
+        let unique: Vec<u64> = kept.iter().map(|&i| fingerprints[i]).collect();
+
+        let stuck = |remaining: &[u64], attempts: u64| StuckBucket {
+            bucket: 0,
+            keys: remaining
+                .iter()
+                .filter_map(|fingerprint| unique.binary_search(fingerprint).ok())
+                .map(|k| keys[kept[k]].to_string())
+                .collect(),
+            attempts,
+            timed_out: false,
+        };
+
+        match self.algorithm {
+            Algorithm::BBHash => {
+                let bbhash = BBHash::new(unique.clone(), &self.fo_hash, params.gamma, stage.seed, jobs, verbose)
+                    .map_err(|remaining| stuck(&remaining, bbhash::MAX_LEVELS as u64))?;
+
+                if verbose {
+                    println!(
+                        "Levels: {} ({:.2} bits/key)",
+                        bbhash.num_levels(),
+                        bbhash.size_bits() as f64 / max(1, m) as f64
+                    );
+                }
+
+                self.bbhash = Some(bbhash);
+            }
+            Algorithm::Monotone => {
+                let mut sorted = kept.clone();
+                sorted.sort_by_cached_key(|&i| keys[i].hashable());
+
+                let sorted_keys: Vec<Vec<u8>> = sorted.iter().map(|&i| keys[i].hashable()).collect();
+                let sorted_keys: Vec<&[u8]> = sorted_keys.iter().map(|key| key.as_slice()).collect();
+                let sorted_fingerprints: Vec<u64> = sorted.iter().map(|&i| fingerprints[i]).collect();
+                let bucket_size = stage.bucket_size.ceil() as usize;
+
+                let monotone = Monotone::new(&sorted_keys, &sorted_fingerprints, &self.fo_hash, bucket_size, stage.seed)
+                    .map_err(|attempts| stuck(&unique, attempts))?;
+
+                if verbose {
+                    println!(
+                        "Found {} buckets ({:.2} bits/key)",
+                        monotone.num_buckets,
+                        monotone.size_bits() as f64 / max(1, m) as f64
+                    );
+                }
+
+                self.monotone = Some(monotone);
+            }
+            Algorithm::Chm => {
+                // The keys are kept in input order, the first occurrence of duplicates staying
+                // in place, so slot i holds the i-th distinct key of the input
+                let mut by_input: Vec<usize> = (0..m).collect();
+                by_input.sort_by_key(|&k| kept[k]);
+
+                let mut indices = vec![0; m];
+
+                for (index, k) in by_input.into_iter().enumerate() {
+                    indices[k] = index;
+                }
+
+                let chm = Chm::new(&unique, &indices, &self.fo_hash, stage.seed).map_err(|attempts| stuck(&unique, attempts))?;
+
+                if verbose {
+                    println!(
+                        "Hypergraph peeled in {} attempt(s) ({:.2} bits/key)",
+                        chm.attempts,
+                        chm.size_bits() as f64 / max(1, m) as f64
+                    );
+                }
+
+                self.chm = Some(chm);
+            }
+            _ => {
+                let bdz = Bdz::new(&unique, &self.fo_hash, stage.seed).map_err(|attempts| stuck(&unique, attempts))?;
+
+                if verbose {
+                    println!(
+                        "Hypergraph peeled in {} attempt(s) ({:.2} bits/key)",
+                        bdz.attempts,
+                        bdz.size_bits() as f64 / max(1, m) as f64
+                    );
+                }
+
+                self.bdz = Some(bdz);
+            }
+        }
+
+        drop(unique);
+
+        let mut is_kept = BitVec::with_len(keys.len());
+
+        for &i in kept.iter() {
+            is_kept.set(i);
+        }
+
+        let mut items: Vec<Item> = Vec::with_capacity(m);
+
+        for (i, key) in keys.iter().enumerate() {
+            if !is_kept.get(i) {
+                continue;
+            }
+
+            let fingerprint = fingerprints[i];
+
+            items.push(Item {
+                final_pos: self.fingerprint_index(key, fingerprint).expect("Every kept key has a slot") as u32,
+                data: key.clone(),
+                key: if self.fo_hash.is_64bits() { HashKey::from(fingerprint) } else { HashKey::from(fingerprint as u32) },
+            });
+        }
+
+        self.buckets = vec![Bucket {
+            items,
+            so_hash: self.so_hash.clone(),
+            pilot: 0,
+        }];
+        self.dense_buckets = 1;
+        self.m = m;
+        self.table_size = m;
+        self.range = m;
+
+        return Ok(());
+    }
+
+    // Slot of a key in BBHash, BDZ, CHM and monotone tables, only monotone tables hash more than
+    // its fingerprint
+    fn fingerprint_index(&self, key: &ItemType, fingerprint: u64) -> Option<usize> {
+        if let Some(monotone) = &self.monotone {
+            return monotone.index(&self.fo_hash, &key.hashable(), fingerprint);
+        }
+
+        if let Some(bbhash) = &self.bbhash {
+            return bbhash.index(&self.fo_hash, fingerprint);
+        }
+
+        if let Some(chm) = &self.chm {
+            return Some(chm.index(&self.fo_hash, fingerprint));
+        }
+
+        return self.bdz.as_ref().and_then(|bdz| bdz.index(&self.fo_hash, fingerprint));
+    }
+
+    // Searches the seed of each bucket in its own table, buckets are independent so they are
+    // searched by batches
+    fn build_fks(
+        &mut self,
+        stage: &Stage,
+        params: &BuildParams,
+        deadline: Option<Instant>,
+        verbose: bool,
+    ) -> Result<(), StuckBucket> {
+        let jobs = max(1, params.jobs);
+
+        let fks = Fks::new(self.buckets.iter().map(|bucket| bucket.items.len()));
+
+        self.table_size = fks.table_size;
+        self.range = fks.table_size;
+
+        // Each bucket searches its seeds in its own table, from an empty one
+        let search_of = |bucket_index: usize| SeedSearch {
+            algorithm: stage.algorithm,
+            table_size: fks.sizes[bucket_index] as usize,
+            group_size: 1,
+            stage_seed: stage.seed,
+            max_attempts: params.max_attempts_per_bucket,
+            deadline,
+        };
+
+        let search = |bucket_index: usize, bucket: &Bucket| {
+            return search_of(bucket_index).run(bucket_index, bucket, &vec![false; fks.sizes[bucket_index] as usize], 0);
+        };
+
+        let total = self.buckets.len();
+        let batch_size = jobs * FKS_BUCKETS_PER_JOB;
+        let indices: Vec<usize> = (0..total).collect();
+
+        for batch in indices.chunks(batch_size) {
+            let results: Vec<SearchResult> = if jobs > 1 {
+                let buckets = &self.buckets;
+                let search = &search;
+
+                std::thread::scope(|scope| {
+                    let handles: Vec<_> = batch
+                        .chunks(batch.len().div_ceil(jobs))
+                        .map(|chunk| {
+                            scope.spawn(move || chunk.iter().map(|&i| search(i, &buckets[i])).collect::<Vec<_>>())
+                        })
+                        .collect();
+
+                    handles
+                        .into_iter()
+                        .flat_map(|h| h.join().expect("Seed search thread panicked"))
+                        .collect()
+                })
+            } else {
+                batch.iter().map(|&i| search(i, &self.buckets[i])).collect()
+            };
+
+            for (&bucket_index, result) in batch.iter().zip(results) {
+                let bucket = &mut self.buckets[bucket_index];
+
+                match result {
+                    SearchResult::Found { attempt, positions } => {
+                        bucket.so_hash.set_seed(search_of(bucket_index).seed(bucket_index, bucket.so_hash.is_64bits(), attempt));
+                        bucket.pilot = attempt;
+
+                        for (item, pos) in bucket.items.iter_mut().zip(positions) {
+                            item.final_pos = fks.offsets[bucket_index] + pos;
+                        }
+                    }
+                    SearchResult::Stuck { attempts, timed_out } => {
+                        return Err(StuckBucket {
+                            bucket: bucket_index,
+                            keys: bucket.items.iter().map(|item| item.data.to_string()).collect(),
+                            attempts,
+                            timed_out,
+                        });
+                    }
+                }
+            }
+
+            if verbose {
+                let done = batch[batch.len() - 1] + 1;
+                print!("\rProgress: {}/{} ({:.1}%)   ", done, total, (done as f64 / total as f64) * 100.0);
+            }
+        }
+
+        if verbose {
+            println!();
+            println!(
+                "Second-level tables: {} slots ({:.2} slots/key)",
+                fks.table_size,
+                fks.table_size as f64 / max(1, self.m) as f64
+            );
+        }
+
+        self.fks = Some(fks);
+
+        return Ok(());
+    }
+
+    // Searches the splitting trees of the buckets by batches, the trees only depend on the stage seed
+    fn build_recsplit(
+        &mut self,
+        stage: &Stage,
+        params: &BuildParams,
+        deadline: Option<Instant>,
+        verbose: bool,
+    ) -> Result<(), StuckBucket> {
+        let jobs = max(1, params.jobs);
+
+        let max_size = self.buckets.iter().map(|bucket| bucket.items.len()).max().unwrap_or(0);
+        let splits = Splits::new(params.leaf_size, params.fanout, max_size);
+        let start_seeds = TreeSearch::start_seeds(stage.seed, splits.entries[max_size].depth, self.so_hash.is_64bits());
+
+        let search = TreeSearch {
+            splits: &splits,
+            so_hash: self.so_hash.clone(),
+            start_seeds: &start_seeds,
+            max_attempts: params.max_attempts_per_bucket,
+            deadline,
+        };
+
+        let total = self.buckets.len();
+        let batch_size = jobs * SPECULATIVE_BUCKETS_PER_JOB;
+        let mut trees: Vec<Tree> = Vec::with_capacity(total);
+
+        for start in (0..total).step_by(batch_size) {
+            let batch: Vec<Vec<HashKey>> = self.buckets[start..min(total, start + batch_size)]
+                .iter()
+                .map(|bucket| bucket.items.iter().map(|item| item.key()).collect())
+                .collect();
+
+            let results: Vec<Result<Tree, recsplit::Stuck>> = if jobs > 1 {
+                let search = &search;
+
+                std::thread::scope(|scope| {
+                    let handles: Vec<_> = batch
+                        .chunks(batch.len().div_ceil(jobs))
+                        .map(|chunk| {
+                            scope.spawn(move || chunk.iter().map(|keys| search.run(keys)).collect::<Vec<_>>())
+                        })
+                        .collect();
+
+                    handles
+                        .into_iter()
+                        .flat_map(|h| h.join().expect("Seed search thread panicked"))
+                        .collect()
+                })
+            } else {
+                batch.iter().map(|keys| search.run(keys)).collect()
+            };
+
+            for (i, result) in results.into_iter().enumerate() {
+                match result {
+                    Ok(tree) => trees.push(tree),
+                    Err(stuck) => {
+                        return Err(StuckBucket {
+                            bucket: start + i,
+                            keys: self.buckets[start + i].items.iter().map(|item| item.data.to_string()).collect(),
+                            attempts: stuck.attempts,
+                            timed_out: stuck.timed_out,
+                        });
+                    }
+                }
+            }
+
+            if verbose {
+                print!(
+                    "\rProgress: {}/{} ({:.1}%)   ",
+                    trees.len(),
+                    total,
+                    (trees.len() as f64 / total as f64) * 100.0
+                );
+            }
+        }
+
+        if verbose {
+            println!();
+        }
+
+        let mut first_pos = 0;
+
+        for (bucket, tree) in self.buckets.iter_mut().zip(trees.iter()) {
+            for (item, &pos) in bucket.items.iter_mut().zip(tree.positions.iter()) {
+                item.final_pos = first_pos + pos;
+            }
+
+            first_pos += bucket.items.len() as u32;
+        }
+
+        let recsplit = RecSplit::new(splits, start_seeds, &trees);
+
+        if verbose {
+            println!(
+                "Splitting trees: {} bits ({:.2} bits/key)",
+                recsplit.size_bits(),
+                recsplit.size_bits() as f64 / max(1, self.m) as f64
+            );
+        }
+
+        self.recsplit = Some(recsplit);
+
+        return Ok(());
+    }
+
+    // Moves the keys placed past m to the free slots below m, keeping the table minimal
+    fn remap_slots(&mut self, occupied: &[bool]) {
+        let m = self.m;
+
+        let mut free_slots = (0..m).filter(|&pos| !occupied[pos]);
+
+        self.remap = vec![0; self.range - m];
+
+        for (pos, &is_occupied) in occupied.iter().enumerate().skip(m) {
+            if is_occupied {
+                self.remap[pos - m] = free_slots.next().expect("As many free slots as keys past m") as u32;
+            }
+        }
+
+        for bucket in self.buckets.iter_mut() {
+            for item in bucket.items.iter_mut() {
+                if item.final_pos as usize >= m {
+                    item.final_pos = self.remap[item.final_pos as usize - m];
+                }
+            }
+        }
+
+        self.table_size = m;
+    }
+
+    /// Bucket of a first-order hash. PTHash tables send most keys to a few dense buckets
+    pub fn bucket_of(&self, key: HashKey) -> usize {
+        let n = self.buckets.len() as u64;
+
+        if !self.algorithm.uses_skewed_buckets() {
+            return (key % n) as usize;
+        }
+
+        let key = key.as_u64();
+        let dense = self.dense_buckets as u64;
+        let x = key / PTHASH_DENSE_KEYS_DIVISOR;
+
+        if key % PTHASH_DENSE_KEYS_DIVISOR < PTHASH_DENSE_KEYS {
+            return (x % dense) as usize;
+        }
+
+        return (dense + x % (n - dense)) as usize;
+    }
+
+    pub fn m(&self) -> usize {
+        return self.m;
+    }
+
+    /// Number of slots of a cache line, given the key stored in each slot (a pointer for strings)
+    fn default_group_size(item: Option<&Item>) -> usize {
+        let slot_bytes = match item.map(|item| item.item_type()) {
+            Some(ItemType::I32(_)) | Some(ItemType::U32(_)) => 4,
+            _ => 8,
+        };
+
+        return CACHE_LINE_BYTES / slot_bytes;
+    }
+
+    /// Dense ranges of integer keys, which are not in the perfect hash table
+    pub fn dense(&self) -> Option<&DenseRanges> {
+        return self.dense.as_ref();
+    }
+
+    /// gperf file the keys were read from
+    pub fn gperf(&self) -> Option<&Gperf> {
+        return self.gperf.as_ref();
+    }
+
+    /// Typed values of the keys, looked up along with them
+    pub fn values(&self) -> Option<&Values> {
+        return self.values.as_ref();
+    }
+
+    /// Transforms applied to the keys, and to the looked up key
+    pub fn normalization(&self) -> &Normalization {
+        return &self.normalization;
+    }
+
+    pub fn fks(&self) -> Option<&Fks> {
+        return self.fks.as_ref();
+    }
+
+    /// Maximum number of keys per slot group, 1 for tables which are not k-perfect
+    pub fn group_size(&self) -> usize {
+        return max(1, self.group_size);
+    }
+
+    /// Size of the generated seeds table in bytes
+    pub fn seeds_size(&self) -> usize {
+        if let Some(recsplit) = &self.recsplit {
+            return recsplit.size_bits().div_ceil(8);
+        }
+
+        if let Some(bbhash) = &self.bbhash {
+            return bbhash.size_bits().div_ceil(8);
+        }
+
+        if let Some(bdz) = &self.bdz {
+            return bdz.size_bits().div_ceil(8);
+        }
+
+        if let Some(chm) = &self.chm {
+            return chm.size_bits().div_ceil(8);
+        }
+
+        if let Some(monotone) = &self.monotone {
+            return monotone.size_bits().div_ceil(8);
+        }
+
+        if let Some(retrieval) = &self.retrieval {
+            return retrieval.size_bits().div_ceil(8);
+        }
+
+        let seed_size = if self.so_hash.is_64bits() { 8 } else { 4 };
+
+        // FKS tables also store the offset and size of the table of each bucket
+        let tables_size = self.fks.as_ref().map_or(0, |fks| fks.size_bits().div_ceil(8));
+
+        return self.buckets.len() * seed_size + tables_size;
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        return self.algorithm;
+    }
+
+    /// Number of dense buckets of PTHash tables, the number of buckets otherwise
+    pub fn dense_buckets(&self) -> usize {
+        return self.dense_buckets;
+    }
+
+    /// Number of positions the second-order hash maps to, past m for tables searched with
+    /// a load factor below 1
+    pub fn range(&self) -> usize {
+        return self.range;
+    }
+
+    /// Slots of the keys placed past m in PTHash tables, indexed by position - m
+    pub fn remap(&self) -> &Vec<u32> {
+        return &self.remap;
+    }
+
+    /// Encoded splitting trees of RecSplit tables
+    pub fn recsplit(&self) -> Option<&RecSplit> {
+        return self.recsplit.as_ref();
+    }
+
+    /// Levels of BBHash tables
+    pub fn bbhash(&self) -> Option<&BBHash> {
+        return self.bbhash.as_ref();
+    }
+
+    /// Hypergraph of BDZ tables
+    pub fn bdz(&self) -> Option<&Bdz> {
+        return self.bdz.as_ref();
+    }
+
+    /// Hypergraph of order-preserving CHM tables
+    pub fn chm(&self) -> Option<&Chm> {
+        return self.chm.as_ref();
+    }
+
+    /// Functions of monotone tables
+    pub fn monotone(&self) -> Option<&Monotone> {
+        return self.monotone.as_ref();
+    }
+
+    /// Static function of retrieval tables
+    pub fn retrieval(&self) -> Option<&StaticFunction> {
+        return self.retrieval.as_ref();
+    }
+
+    /// Seed of the construction stage that built the table
+    pub fn seed(&self) -> u64 {
+        return self.seed;
+    }
+
+    /// Number of slots of the table, greater than m for non-minimal tables
+    pub fn table_size(&self) -> usize {
+        return self.table_size;
+    }
+
+    pub fn bucket_size(&self) -> f64 {
+        return self.bucket_size;
+    }
+
+    pub fn fo_hash(&self) -> &FOHash {
+        return &self.fo_hash;
+    }
+
+    pub fn so_hash(&self) -> &SOHash {
+        return &self.so_hash;
+    }
+
+    pub fn buckets(&self) -> &Buckets {
+        return &self.buckets;
+    }
+
+    pub fn first_bucket(&self) -> Option<&Bucket> {
+        return self.buckets.first();
+    }
+
+    /// Seed of the first-order hash giving the fingerprints of the keys, independent from the
+    /// one placing them
+    pub fn fingerprint_seed(&self) -> u64 {
+        let seed = splitmix64(self.seed ^ FINGERPRINT_SEED_SALT);
+
+        if self.fo_hash.is_64bits() {
+            return seed;
+        }
+
+        return seed as u32 as u64;
+    }
+
+    /// Fingerprint of the given number of bits of a key, stored instead of the key by membership
+    /// tables
+    pub fn fingerprint(&self, key: &ItemType, bits: u32) -> u64 {
+        let hash = self.fo_hash.hash_with_seed(&key.hashable(), self.fingerprint_seed()).as_u64();
+
+        return hash & (u64::MAX >> (64 - bits));
+    }
+
+    pub fn first_item(&self) -> Option<&Item> {
+        for bucket in self.buckets.iter() {
+            if bucket.items.is_empty() {
+                continue;
+            }
+
+            return bucket.items.first();
+        }
+
+        return None;
+    }
+
+    /// Items indexed by their slot, empty slots of non-minimal tables are None
+    pub fn slots(&self) -> Vec<Option<&Item>> {
+        let mut result = vec![None; self.table_size];
+
+        for bucket in self.buckets.iter() {
+            for item in bucket.items.iter() {
+                result[item.final_pos as usize] = Some(item);
+            }
+        }
+
+        return result;
+    }
+
+    pub fn items(&self) -> Vec<&Item> {
+        return self.slots().into_iter().flatten().collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(algorithm: Algorithm) -> BuildParams {
+        return BuildParams {
+            algorithm,
+            bucket_size: algorithm.default_bucket_size(),
+            load_factor: algorithm.default_load_factor(),
+            jobs: 1,
+            seed: Some(1),
+            verbose: false,
+            ..BuildParams::default()
+        };
+    }
+
+    fn words(n: usize) -> Vec<ItemType> {
+        return (0..n).map(|i| ItemType::Str(format!("word{i}"))).collect();
+    }
+
+    #[test]
+    fn fallback_stages_halve_the_buckets_then_try_other_hashes_and_load_factors() {
+        let params = BuildParams { bucket_size: 8.0, ..params(Algorithm::Chd) };
+        let stages = PHash::stages(&params, 1);
+
+        let summary: Vec<(f64, &str, f64)> = stages
+            .iter()
+            .map(|stage| (stage.bucket_size, stage.second_order_hash.as_str(), stage.load_factor))
+            .collect();
+
+        assert_eq!(
+            summary,
+            [
+                (8.0, "xorshift", 1.0),
+                (4.0, "xorshift", 1.0),
+                (2.0, "xorshift", 1.0),
+                (2.0, "mxf", 1.0),
+                (2.0, "mxf64", 1.0),
+                (2.0, "xorshift", 0.9),
+                (2.0, "xorshift", 0.75),
+                (2.0, "xorshift", 0.5),
+                (1.0, "xorshift", 0.5),
+            ]
+        );
+
+        // Each stage searches its own seeds
+        let seeds: HashSet<u64> = stages.iter().map(|stage| stage.seed).collect();
+        assert_eq!(seeds.len(), stages.len());
+    }
+
+    #[test]
+    fn minimal_tables_and_disabled_fallbacks_have_fewer_stages() {
+        let recsplit = PHash::stages(&params(Algorithm::RecSplit), 1);
+        assert!(recsplit.iter().all(|stage| stage.load_factor == 1.0));
+        assert_eq!(recsplit.last().map(|stage| stage.bucket_size), Some(LAST_RESORT_BUCKET_SIZE));
+
+        let no_fallback = BuildParams { fallbacks: false, ..params(Algorithm::Chd) };
+        assert_eq!(PHash::stages(&no_fallback, 1).len(), 1);
+        assert_eq!(PHash::stages(&params(Algorithm::Bdz), 1).len(), 1);
+    }
+
+    #[test]
+    fn stuck_buckets_name_their_keys_and_attempts() {
+        let stuck = StuckBucket {
+            bucket: 3,
+            keys: (0..10).map(|i| format!("\"k{i}\"")).collect(),
+            attempts: 5,
+            timed_out: false,
+        };
+
+        assert_eq!(
+            stuck.to_string(),
+            r#"bucket 3 (10 keys: "k0", "k1", "k2", "k3", "k4", "k5", "k6", "k7", ...) could not be placed after 5 attempts"#
+        );
+
+        let timed_out = StuckBucket { timed_out: true, ..stuck };
+        assert!(timed_out.to_string().ends_with("was still unplaced when the timeout was reached (5 attempts)"));
+
+        let params = BuildParams {
+            max_attempts_per_bucket: Some(1),
+            fallbacks: false,
+            ..params(Algorithm::Chd)
+        };
+
+        let e = PHash::from_keys(&words(1000), &params).unwrap_err().to_string();
+        assert!(e.starts_with("Cannot build the perfect hash table, bucket "), "{e}");
+        assert!(e.ends_with("could not be placed after 1 attempts"), "{e}");
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_table_whatever_the_jobs() {
+        let keys = words(5000);
+
+        for algorithm in [Algorithm::Chd, Algorithm::PTHash, Algorithm::RecSplit, Algorithm::KPerfect, Algorithm::Fks] {
+            let layout = |jobs: usize| {
+                let phash = PHash::from_keys(&keys, &BuildParams { jobs, ..params(algorithm) }).unwrap();
+
+                let slots: Vec<(ItemType, u32)> = phash.items().iter().map(|item| (item.item_type().clone(), item.final_pos)).collect();
+                let seeds: Vec<u64> = phash.buckets().iter().map(|bucket| u64::from(bucket.so_hash().seed())).collect();
+
+                return (slots, seeds);
+            };
+
+            assert!(layout(1) == layout(4), "{}", algorithm.name());
+        }
+    }
+
+    #[test]
+    fn colliding_32_bits_hashes_fall_back_on_xxhash64() {
+        // Same murmur3 hash with the default seed
+        let keys: Vec<ItemType> = ["key168173", "key169345", "alpha", "bravo"]
+            .iter()
+            .map(|key| ItemType::Str(key.to_string()))
+            .collect();
+
+        let phash = PHash::from_keys(&keys, &params(Algorithm::Chd)).unwrap();
+        assert_eq!(phash.fo_hash().name(), "xxhash64");
+
+        let no_fallback = BuildParams { fallbacks: false, ..params(Algorithm::Chd) };
+        let e = PHash::from_keys(&keys, &no_fallback).unwrap_err();
+        assert!(e.to_string().contains("have the same murmur3 hash"), "{e}");
     }
 }
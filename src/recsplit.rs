@@ -0,0 +1,517 @@
+use super::bits::{BitVec, EliasFano};
+use super::hash::{splitmix64, HashKey, HashSeed, SOHash};
+use std::num::NonZeroUsize;
+use std::time::Instant;
+
+// https://arxiv.org/pdf/1910.06416
+
+pub const DEFAULT_LEAF_SIZE: usize = 8;
+pub const DEFAULT_FANOUT: usize = 4;
+
+// Keys per bucket, the splitting trees get more compact as buckets grow
+pub const DEFAULT_BUCKET_SIZE: f64 = 100.0;
+
+// Leaves are searched for a bijection, whose probability drops quickly with their size
+pub const MAX_LEAF_SIZE: usize = 24;
+
+// Attempts between two checks of the deadline
+const DEADLINE_CHECK_INTERVAL: u64 = 256;
+
+/// Node of the splitting trees of a given size
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Split {
+    /// Size of the parts the node is split into, the last one holding the remainder. 0 for leaves
+    pub unit: usize,
+    /// Number of low bits of the node seed stored verbatim, the rest is stored in unary
+    pub rice: usize,
+    /// Number of fixed bits of the subtree rooted at the node
+    pub skip_bits: usize,
+    /// Number of nodes of the subtree rooted at the node
+    pub skip_nodes: usize,
+    /// Depth of the subtree rooted at the node
+    pub depth: usize,
+}
+
+/// Shape of the splitting trees, only depending on the size of the bucket
+#[derive(Debug, Clone, Default)]
+pub struct Splits {
+    pub leaf_size: usize,
+    pub fanout: usize,
+    /// Indexed by node size
+    pub entries: Vec<Split>,
+}
+
+impl Splits {
+    pub fn new(leaf_size: usize, fanout: usize, max_size: usize) -> Splits {
+        let mut entries = vec![Split::default(); max_size + 1];
+
+        for size in 2..=max_size {
+            let unit = Splits::unit(leaf_size, fanout, size);
+            let rice = Splits::rice(Splits::probability(size, unit));
+
+            let mut split = Split {
+                unit,
+                rice,
+                skip_bits: rice,
+                skip_nodes: 1,
+                depth: 1,
+            };
+
+            if unit > 0 {
+                let mut remaining = size;
+
+                while remaining > 0 {
+                    let part = &entries[unit.min(remaining)];
+                    split.skip_bits += part.skip_bits;
+                    split.skip_nodes += part.skip_nodes;
+                    split.depth = split.depth.max(part.depth + 1);
+                    remaining -= unit.min(remaining);
+                }
+            }
+
+            entries[size] = split;
+        }
+
+        return Splits {
+            leaf_size,
+            fanout,
+            entries,
+        };
+    }
+
+    // Leaves up to leaf_size keys, then fanout-way splits in leaves, then fanout-way splits in
+    // groups of leaves, and binary splits above that
+    fn unit(leaf_size: usize, fanout: usize, size: usize) -> usize {
+        let lower = leaf_size * fanout;
+        let upper = lower * fanout;
+
+        if size <= leaf_size {
+            return 0;
+        }
+
+        if size <= lower {
+            return leaf_size;
+        }
+
+        if size <= upper {
+            return lower;
+        }
+
+        return size.div_ceil(2).div_ceil(upper) * upper;
+    }
+
+    // Probability that a random seed splits a node as expected
+    fn probability(size: usize, unit: usize) -> f64 {
+        let log_factorial = |n: usize| (2..=n).map(|i| (i as f64).ln()).sum::<f64>();
+
+        let s = size as f64;
+
+        if unit == 0 {
+            return (log_factorial(size) - s * s.ln()).exp();
+        }
+
+        let mut log_p = log_factorial(size);
+        let mut remaining = size;
+
+        while remaining > 0 {
+            let part = unit.min(remaining);
+            log_p += (part as f64) * (part as f64 / s).ln() - log_factorial(part);
+            remaining -= part;
+        }
+
+        return log_p.exp();
+    }
+
+    // Golomb-Rice parameter for the number of attempts, geometrically distributed
+    fn rice(p: f64) -> usize {
+        if p >= 1.0 {
+            return 0;
+        }
+
+        let golden_ratio = (5.0f64.sqrt() + 1.0) / 2.0;
+        let k = 1.0 + ((golden_ratio - 1.0).ln() / (-p).ln_1p()).log2().floor();
+
+        return k.max(0.0) as usize;
+    }
+}
+
+/// Splitting tree of a bucket
+#[derive(Debug, Clone, Default)]
+pub struct Tree {
+    /// Size and seed index of the nodes, in depth-first order
+    pub nodes: Vec<(usize, u64)>,
+    /// Position of each key of the bucket within the bucket
+    pub positions: Vec<u32>,
+}
+
+/// A node for which no seed could be found within the limits
+#[derive(Debug, Clone, Copy)]
+pub struct Stuck {
+    pub attempts: u64,
+    pub timed_out: bool,
+}
+
+// Seed search of the splitting trees of a construction stage
+pub struct TreeSearch<'a> {
+    pub splits: &'a Splits,
+    pub so_hash: SOHash,
+    pub start_seeds: &'a [u64],
+    pub max_attempts: Option<u64>,
+    pub deadline: Option<Instant>,
+}
+
+impl TreeSearch<'_> {
+    /// First seed of the nodes at each depth, so that nodes at different depths are split independently
+    pub fn start_seeds(stage_seed: u64, max_depth: usize, is_64bits: bool) -> Vec<u64> {
+        return (0..max_depth.max(1))
+            .map(|depth| {
+                let seed = splitmix64(stage_seed ^ depth as u64) | 1;
+
+                if is_64bits {
+                    return seed;
+                }
+
+                return seed as u32 as u64;
+            })
+            .collect();
+    }
+
+    fn seed(&self, depth: usize, attempt: u64) -> HashSeed {
+        let seed = self.start_seeds[depth].wrapping_add(attempt.wrapping_mul(2));
+
+        if self.so_hash.is_64bits() {
+            return HashSeed::from(seed);
+        }
+
+        return HashSeed::from(seed as u32);
+    }
+
+    /// Builds the splitting tree of a bucket
+    pub fn run(&self, keys: &[HashKey]) -> Result<Tree, Stuck> {
+        let mut tree = Tree {
+            nodes: Vec::new(),
+            positions: vec![0; keys.len()],
+        };
+
+        let mut indexed: Vec<(HashKey, usize)> = keys.iter().copied().zip(0..).collect();
+
+        self.split(&mut indexed, 0, 0, &mut tree)?;
+
+        return Ok(tree);
+    }
+
+    fn split(&self, keys: &mut [(HashKey, usize)], depth: usize, offset: u32, tree: &mut Tree) -> Result<(), Stuck> {
+        let size = keys.len();
+
+        if size <= 1 {
+            if let Some(&(_, index)) = keys.first() {
+                tree.positions[index] = offset;
+            }
+
+            return Ok(());
+        }
+
+        let unit = self.splits.entries[size].unit;
+        let node = tree.nodes.len();
+        tree.nodes.push((size, 0));
+
+        let mut so_hash = self.so_hash.clone();
+        let mut hashes = vec![0u32; size];
+        let mut counts = vec![0usize; if unit > 0 { size.div_ceil(unit) } else { 0 }];
+        let mut used = vec![false; size];
+        let mut attempt = 0;
+
+        loop {
+            let timed_out = attempt % DEADLINE_CHECK_INTERVAL == 0 && self.deadline.is_some_and(|d| Instant::now() >= d);
+
+            if timed_out || self.max_attempts.is_some_and(|max| attempt >= max) {
+                return Err(Stuck {
+                    attempts: attempt,
+                    timed_out,
+                });
+            }
+
+            so_hash.set_seed(self.seed(depth, attempt));
+
+            for (hash, (key, _)) in hashes.iter_mut().zip(keys.iter()) {
+                *hash = so_hash.hash(*key) % size as u32;
+            }
+
+            let found = match NonZeroUsize::new(unit) {
+                None => {
+                    used.fill(false);
+                    hashes.iter().all(|&h| !std::mem::replace(&mut used[h as usize], true))
+                }
+                Some(unit) => {
+                    counts.fill(0);
+
+                    for &h in hashes.iter() {
+                        counts[h as usize / unit] += 1;
+                    }
+
+                    counts.iter().enumerate().all(|(part, &count)| count == unit.get().min(size - part * unit.get()))
+                }
+            };
+
+            if found {
+                break;
+            }
+
+            attempt += 1;
+        }
+
+        tree.nodes[node].1 = attempt;
+
+        if unit == 0 {
+            for (&h, &(_, index)) in hashes.iter().zip(keys.iter()) {
+                tree.positions[index] = offset + h;
+            }
+
+            return Ok(());
+        }
+
+        let mut parts: Vec<(usize, (HashKey, usize))> =
+            hashes.iter().zip(keys.iter()).map(|(&h, &key)| (h as usize / unit, key)).collect();
+        parts.sort_by_key(|&(part, _)| part);
+
+        for (key, (_, sorted)) in keys.iter_mut().zip(parts) {
+            *key = sorted;
+        }
+
+        for (part, chunk) in keys.chunks_mut(unit).enumerate() {
+            self.split(chunk, depth + 1, offset + (part * unit) as u32, tree)?;
+        }
+
+        return Ok(());
+    }
+}
+
+/// Splitting trees of all the buckets, Golomb-Rice encoded, with the Elias-Fano encoded
+/// first position and first bit of each bucket
+#[derive(Debug, Clone, Default)]
+pub struct RecSplit {
+    pub splits: Splits,
+    pub start_seeds: Vec<u64>,
+    pub tree: BitVec,
+    pub positions: EliasFano,
+    pub offsets: EliasFano,
+}
+
+impl RecSplit {
+    /// Encodes the trees of the buckets, in bucket order
+    pub fn new(splits: Splits, start_seeds: Vec<u64>, trees: &[Tree]) -> RecSplit {
+        let mut tree = BitVec::new();
+        let mut positions = vec![0u64];
+        let mut offsets = vec![0u64];
+
+        for bucket in trees.iter() {
+            // Fixed bits of all the nodes first, so that subtrees can be skipped without decoding them
+            for &(size, seed) in bucket.nodes.iter() {
+                tree.push_bits(seed, splits.entries[size].rice);
+            }
+
+            for &(size, seed) in bucket.nodes.iter() {
+                tree.push_unary(seed >> splits.entries[size].rice);
+            }
+
+            positions.push(positions.last().unwrap() + bucket.positions.len() as u64);
+            offsets.push(tree.len() as u64);
+        }
+
+        return RecSplit {
+            splits,
+            start_seeds,
+            tree,
+            positions: EliasFano::new(&positions),
+            offsets: EliasFano::new(&offsets),
+        };
+    }
+
+    /// Size of the encoded trees and bucket offsets in bits
+    pub fn size_bits(&self) -> usize {
+        return self.tree.len() + self.positions.size_bits() + self.offsets.size_bits();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::XorShift;
+
+    fn keys(n: usize, salt: u64) -> Vec<HashKey> {
+        return (0..n as u64).map(|i| HashKey::from(splitmix64(i ^ (salt << 32)) as u32)).collect();
+    }
+
+    fn trees(splits: &Splits, start_seeds: &[u64], buckets: &[Vec<HashKey>]) -> Vec<Tree> {
+        let search = TreeSearch {
+            splits,
+            so_hash: SOHash::XorShift(XorShift::default()),
+            start_seeds,
+            max_attempts: None,
+            deadline: None,
+        };
+
+        return buckets.iter().map(|keys| search.run(keys).unwrap()).collect();
+    }
+
+    // Reads back the seeds of the nodes of every bucket, fixed bits first then unary parts
+    fn decode(recsplit: &RecSplit, trees: &[Tree]) -> (Vec<Vec<u64>>, Vec<u64>) {
+        let mut pos = 0;
+        let mut offsets = vec![0];
+        let mut seeds = Vec::new();
+
+        for tree in trees.iter() {
+            let mut bucket: Vec<u64> = Vec::new();
+
+            for &(size, _) in tree.nodes.iter() {
+                let mut fixed = 0;
+
+                for bit in 0..recsplit.splits.entries[size].rice {
+                    if recsplit.tree.get(pos) {
+                        fixed |= 1 << bit;
+                    }
+
+                    pos += 1;
+                }
+
+                bucket.push(fixed);
+            }
+
+            for (seed, &(size, _)) in bucket.iter_mut().zip(tree.nodes.iter()) {
+                let mut unary = 0;
+
+                while !recsplit.tree.get(pos) {
+                    unary += 1;
+                    pos += 1;
+                }
+
+                pos += 1;
+                *seed |= unary << recsplit.splits.entries[size].rice;
+            }
+
+            seeds.push(bucket);
+            offsets.push(pos as u64);
+        }
+
+        assert_eq!(pos, recsplit.tree.len());
+
+        return (seeds, offsets);
+    }
+
+    // Walks down the splitting tree of a bucket like the generated lookups do, skipping the
+    // subtrees of the parts before the one of the key
+    fn locate(search: &TreeSearch, seeds: &[u64], size: usize, key: HashKey) -> u32 {
+        let mut size = size;
+        let mut node = 0;
+        let mut depth = 0;
+        let mut offset = 0;
+        let mut so_hash = search.so_hash.clone();
+
+        while size > 1 {
+            so_hash.set_seed(search.seed(depth, seeds[node]));
+
+            let h = (so_hash.hash(key) % size as u32) as usize;
+            let unit = search.splits.entries[size].unit;
+
+            if unit == 0 {
+                return offset + h as u32;
+            }
+
+            let part = h / unit;
+
+            node += 1;
+
+            for p in 0..part {
+                node += search.splits.entries[unit.min(size - p * unit)].skip_nodes;
+            }
+
+            offset += (part * unit) as u32;
+            size = unit.min(size - part * unit);
+            depth += 1;
+        }
+
+        return offset;
+    }
+
+    #[test]
+    fn golomb_rice_round_trip() {
+        let splits = Splits::new(DEFAULT_LEAF_SIZE, DEFAULT_FANOUT, 200);
+        let start_seeds = TreeSearch::start_seeds(42, splits.entries[200].depth, false);
+        let buckets: Vec<Vec<HashKey>> = [0, 1, 2, 7, 8, 9, 33, 100, 200].iter().map(|&n| keys(n, n as u64)).collect();
+        let trees = trees(&splits, &start_seeds, &buckets);
+
+        let recsplit = RecSplit::new(splits.clone(), start_seeds.clone(), &trees);
+        let (seeds, offsets) = decode(&recsplit, &trees);
+
+        for (tree, decoded) in trees.iter().zip(seeds.iter()) {
+            let expected: Vec<u64> = tree.nodes.iter().map(|&(_, seed)| seed).collect();
+            assert_eq!(*decoded, expected);
+        }
+
+        let mut positions = vec![0];
+
+        for bucket in buckets.iter() {
+            positions.push(positions.last().unwrap() + bucket.len() as u64);
+        }
+
+        let expected_offsets = EliasFano::new(&offsets);
+        let expected_positions = EliasFano::new(&positions);
+
+        assert_eq!(recsplit.offsets.upper.words(), expected_offsets.upper.words());
+        assert_eq!(recsplit.offsets.lower.words(), expected_offsets.lower.words());
+        assert_eq!(recsplit.positions.upper.words(), expected_positions.upper.words());
+        assert_eq!(recsplit.positions.lower.words(), expected_positions.lower.words());
+    }
+
+    #[test]
+    fn trees_are_bijections() {
+        let splits = Splits::new(DEFAULT_LEAF_SIZE, DEFAULT_FANOUT, 200);
+        let start_seeds = TreeSearch::start_seeds(7, splits.entries[200].depth, false);
+
+        let search = TreeSearch {
+            splits: &splits,
+            so_hash: SOHash::XorShift(XorShift::default()),
+            start_seeds: &start_seeds,
+            max_attempts: None,
+            deadline: None,
+        };
+
+        for n in [1, 2, 8, 9, 32, 33, 127, 200] {
+            let bucket = keys(n, 1000 + n as u64);
+            let tree = search.run(&bucket).unwrap();
+
+            let mut sorted = tree.positions.clone();
+            sorted.sort_unstable();
+            assert_eq!(sorted, (0..n as u32).collect::<Vec<u32>>(), "bucket of {n} keys");
+
+            let seeds: Vec<u64> = tree.nodes.iter().map(|&(_, seed)| seed).collect();
+
+            for (key, &position) in bucket.iter().zip(tree.positions.iter()) {
+                assert_eq!(locate(&search, &seeds, n, *key), position, "bucket of {n} keys");
+            }
+
+            assert_eq!(tree.nodes.len(), splits.entries[n].skip_nodes);
+        }
+    }
+
+    #[test]
+    fn empty_and_single_key_buckets() {
+        let splits = Splits::new(DEFAULT_LEAF_SIZE, DEFAULT_FANOUT, 8);
+        let start_seeds = TreeSearch::start_seeds(0, 1, false);
+        let trees = trees(&splits, &start_seeds, &[Vec::new(), keys(1, 0)]);
+
+        assert!(trees[0].nodes.is_empty() && trees[0].positions.is_empty());
+        assert!(trees[1].nodes.is_empty());
+        assert_eq!(trees[1].positions, vec![0]);
+
+        let recsplit = RecSplit::new(splits.clone(), start_seeds.clone(), &trees);
+
+        assert!(recsplit.tree.is_empty());
+
+        let empty = RecSplit::new(splits, start_seeds, &[]);
+
+        assert!(empty.tree.is_empty());
+        assert_eq!(empty.positions.samples, vec![0]);
+        assert_eq!(empty.offsets.samples, vec![0]);
+    }
+}
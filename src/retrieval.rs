@@ -0,0 +1,93 @@
+use super::bdz::{self, Bdz};
+use super::bits::BitVec;
+use super::hash::FOHash;
+
+/// Static function over the 3-uniform hypergraph of BDZ tables: every vertex stores a value of
+/// `width` bits, and the values of the three vertices of a key xor to the value of the key.
+/// Keys are not stored, other keys get an arbitrary value
+#[derive(Debug, Clone, Default)]
+pub struct StaticFunction {
+    /// Seed of the first-order hash of each of the three parts of the vertices
+    pub vertex_seeds: Vec<u64>,
+    /// Number of vertices of each part
+    pub part_size: usize,
+    /// Number of bits of each value
+    pub width: usize,
+    /// Values of the vertices, packed
+    pub values: BitVec,
+    pub attempts: u64,
+}
+
+impl StaticFunction {
+    /// Builds the function mapping the i-th fingerprint, which must be distinct, to values[i].
+    /// Returns the number of attempts if no attempt gave a peelable hypergraph
+    pub fn new(fingerprints: &[u64], values: &[u64], fo_hash: &FOHash, stage_seed: u64) -> Result<StaticFunction, u64> {
+        let width = (u64::BITS - values.iter().max().copied().unwrap_or(0).leading_zeros()) as usize;
+        let part_size = Bdz::part_size(fingerprints.len());
+
+        for attempt in 0..bdz::MAX_ATTEMPTS {
+            let vertex_seeds = Bdz::vertex_seeds(fo_hash, stage_seed, attempt);
+
+            let edges: Vec<[usize; 3]> = fingerprints
+                .iter()
+                .map(|&fingerprint| Bdz::edge(fo_hash, fingerprint, &vertex_seeds, part_size))
+                .collect();
+
+            let Some(order) = Bdz::peel(&edges, 3 * part_size) else {
+                continue;
+            };
+
+            let mut g = vec![0u64; 3 * part_size];
+
+            for &(edge, free) in order.iter().rev() {
+                g[free] = edges[edge].iter().filter(|&&v| v != free).fold(values[edge], |x, &v| x ^ g[v]);
+            }
+
+            let mut packed = BitVec::new();
+
+            for value in g {
+                packed.push_bits(value, width);
+            }
+
+            return Ok(StaticFunction {
+                vertex_seeds,
+                part_size,
+                width,
+                values: packed,
+                attempts: attempt + 1,
+            });
+        }
+
+        return Err(bdz::MAX_ATTEMPTS);
+    }
+
+    fn read(&self, vertex: usize) -> u64 {
+        if self.width == 0 {
+            return 0;
+        }
+
+        let pos = vertex * self.width;
+        let words = self.values.words();
+        let offset = pos % 64;
+
+        let mut value = words[pos / 64] >> offset;
+
+        if offset + self.width > 64 {
+            value |= words[pos / 64 + 1] << (64 - offset);
+        }
+
+        return value & (u64::MAX >> (64 - self.width));
+    }
+
+    /// Value of a fingerprint
+    pub fn get(&self, fo_hash: &FOHash, fingerprint: u64) -> u64 {
+        let vertices = Bdz::edge(fo_hash, fingerprint, &self.vertex_seeds, self.part_size);
+
+        return vertices.iter().fold(0, |x, &v| x ^ self.read(v));
+    }
+
+    /// Size of the values in bits
+    pub fn size_bits(&self) -> usize {
+        return self.values.len();
+    }
+}
@@ -0,0 +1,132 @@
+use super::hash::{FOHash, SOHash};
+use super::phash::{Algorithm, BuildParams, ItemType, PHash};
+use std::time::{Duration, Instant};
+
+// Average bucket sizes (lambda) tried for each pair of hashes
+const BUCKET_SIZES: [f64; 9] = [1.0, 1.5, 2.0, 3.0, 4.0, 5.0, 6.0, 8.0, 10.0];
+
+// Seed search budget per bucket, combinations exceeding it are rejected
+const MAX_ATTEMPTS_PER_BUCKET: u64 = 100_000;
+
+// Builds timed per combination, their average is used as the build time
+const BUILD_RUNS: u32 = 3;
+
+// Build times are rounded up to a multiple of this resolution before scoring, so that timer
+// noise on small keysets does not reorder the candidates
+const BUILD_TIME_RESOLUTION: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub first_order_hash: String,
+    pub second_order_hash: String,
+    pub bucket_size: f64,
+    pub build_time: Duration,
+    /// Size of the seeds table in bytes
+    pub table_size: usize,
+    /// Estimated number of operations per lookup
+    pub lookup_cost: f64,
+    /// Sum of the logs of the three metrics above, each normalized by the best one found.
+    /// The build time is averaged over several runs and counted at a 1 ms resolution
+    pub score: f64,
+}
+
+/// Builds the perfect hash of the keyset for every registered pair of hashes and average bucket size,
+/// and returns the valid combinations ranked from best to worst
+pub fn tune(
+    keys: &[ItemType],
+    jobs: usize,
+    seed: Option<u64>,
+) -> Result<Vec<Candidate>, Box<dyn std::error::Error>> {
+    if keys.is_empty() {
+        return Err("Cannot tune an empty keyset".into());
+    }
+
+    let avg_len = keys.iter().map(|k| k.hashable().len()).sum::<usize>() as f64 / keys.len() as f64;
+
+    let mut candidates = Vec::new();
+
+    for fo_hash in FOHash::all().iter() {
+        for so_hash in SOHash::all().iter() {
+            // Would be switched to mxf64 anyway
+            if fo_hash.is_64bits() && !so_hash.is_64bits() {
+                continue;
+            }
+
+            for bucket_size in BUCKET_SIZES {
+                let params = BuildParams {
+                    algorithm: Algorithm::Chd,
+                    first_order_hash: fo_hash.name().to_string(),
+                    second_order_hash: so_hash.name().to_string(),
+                    bucket_size,
+                    load_factor: 1.0,
+                    max_attempts_per_bucket: Some(MAX_ATTEMPTS_PER_BUCKET),
+                    timeout: None,
+                    fallbacks: false,
+                    jobs,
+                    seed,
+                    verbose: false,
+                    ..BuildParams::default()
+                };
+
+                print!("{}/{} with average bucket size {:.1}: ", fo_hash.name(), so_hash.name(), bucket_size);
+
+                let start = Instant::now();
+
+                let phash = match PHash::from_keys(keys, &params) {
+                    Ok(phash) => phash,
+                    Err(e) => {
+                        println!("rejected ({})", e);
+                        continue;
+                    }
+                };
+
+                let mut total_time = start.elapsed();
+
+                // The first build succeeded, the other ones only refine its timing, whatever their outcome
+                for _ in 1..BUILD_RUNS {
+                    let start = Instant::now();
+                    let _ = PHash::from_keys(keys, &params);
+                    total_time += start.elapsed();
+                }
+
+                let build_time = total_time / BUILD_RUNS;
+
+                println!("found in {:.2} ms", build_time.as_secs_f64() * 1000.0);
+
+                candidates.push(Candidate {
+                    first_order_hash: params.first_order_hash,
+                    second_order_hash: params.second_order_hash,
+                    bucket_size,
+                    build_time,
+                    table_size: phash.seeds_size(),
+                    lookup_cost: phash.fo_hash().cost(avg_len) + phash.so_hash().cost(),
+                    score: 0.0,
+                });
+            }
+        }
+    }
+
+    if candidates.is_empty() {
+        return Err("No combination of hashes and average bucket size could build a perfect hash for this keyset".into());
+    }
+
+    // At least one step of the resolution, a null build time would have an infinite score
+    let scored_time = |c: &Candidate| {
+        let steps = c.build_time.as_nanos().div_ceil(BUILD_TIME_RESOLUTION.as_nanos()).max(1);
+        return steps as f64 * BUILD_TIME_RESOLUTION.as_secs_f64();
+    };
+
+    let min_time = candidates.iter().map(scored_time).fold(f64::MAX, f64::min);
+    let min_size = candidates.iter().map(|c| c.table_size).min().unwrap_or(1).max(1) as f64;
+    let min_cost = candidates.iter().map(|c| c.lookup_cost).fold(f64::MAX, f64::min);
+
+    for candidate in candidates.iter_mut() {
+        candidate.score = (scored_time(candidate) / min_time).ln()
+            + (candidate.table_size as f64 / min_size).ln()
+            + (candidate.lookup_cost / min_cost).ln();
+    }
+
+    candidates.sort_by(|a, b| a.score.total_cmp(&b.score));
+
+    return Ok(candidates);
+}